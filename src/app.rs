@@ -1,23 +1,50 @@
 use std::{
-    collections::HashMap, io, sync::{Arc, Mutex}, time::{Duration, Instant}
+    collections::{BTreeMap, HashMap}, io, net::IpAddr, path::Path, time::{Duration, Instant}
 };
-use crate::{net::{self, parse_cidr, Results}, scan::ScanResult, ui::draw};
+use crate::ui::draw;
+use nmap_rs::{engine::{self, Engine, JobId, JobState}, host::{HostResult, HostStatus}, net::ScanEvent, options::ScanOptions, scan::{self, PortList, PortState, ScanResult}, target, Scanner};
 use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
 use ratatui::DefaultTerminal;
 // Refresh time in ms
 const TICK_TIME: Duration = Duration::from_millis(300);
 #[derive(PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
 pub enum FocusZone {
     AddressList,
     PortList,
     InputList,
 }
+/// Probes of a scan: a discovery ping and one probe per port for every host.
+/// Retransmissions are not counted. The total is known once the scan has
+/// expanded its targets.
+#[derive(Clone, Copy, Default)]
+pub struct ProbeCount {
+    per_host: u64,
+    pub total: u64,
+    pub done: u64,
+}
+
 pub struct App {
-    state: Arc<Mutex<Results>>,
-    pub targets: Vec<String>,
-    pub ports: String,
+    engine: Engine,
+    /// Every scan submitted to the engine and where it is in its lifecycle
+    pub jobs: BTreeMap<JobId, JobState>,
+    /// Whether the engine holds back probes
+    pub paused: bool,
+    /// Hosts in the order they were found
+    pub hosts: Vec<IpAddr>,
+    pub ports: PortList,
     pub targets_selected: usize, 
-    pub port_results: HashMap<String, Vec<ScanResult>>,
+    /// What is known about every listed host
+    pub results: HashMap<IpAddr, HostResult>,
+    /// Hosts found down, which are counted rather than listed
+    pub down_hosts: usize,
+    /// Probes of the scans that are running or queued
+    pub probes: BTreeMap<JobId, ProbeCount>,
+    /// Probes completed on hosts still being scanned
+    host_probes: HashMap<IpAddr, u64>,
+    /// When the running scan started
+    running_since: Option<Instant>,
+    pub open_ports: usize,
     pub port_results_selected: usize,
     pub input_selected: usize,
     pub progress: f32,
@@ -28,18 +55,36 @@ pub struct App {
     pub input_mode: bool,
     pub target_input: String,
     pub port_input: String,
-    pub total_targets: usize,
-    pub complete_time: Arc<Mutex<Duration>>
+    pub options_input: String,
+    pub target_file_input: String,
+    /// Targets loaded from a target file or standard input
+    pub file_targets: Vec<String>,
+    /// Seed of the random probe order of every randomized scan
+    pub seeds: HashMap<JobId, u64>,
+    /// Time the last finished scan took, zero while a scan runs
+    pub complete_time: Duration,
+    /// Probes per second over the last tick
+    pub rate: f64,
+    last_probes_sent: u64,
+    /// Distinct errors and warnings of the scans, oldest first
+    pub errors: Vec<String>,
 }
 
 impl App {
-    pub fn init(state: Arc<Mutex<Results>>) -> Self{
+    pub fn init(engine: Engine) -> Self{
         Self {
-            state,
-            targets: Vec::new(),
-            ports: String::new(),
+            engine,
+            jobs: BTreeMap::new(),
+            paused: false,
+            hosts: Vec::new(),
+            ports: PortList::default(),
             targets_selected: 0,
-            port_results: HashMap::new(),
+            results: HashMap::new(),
+            down_hosts: 0,
+            probes: BTreeMap::new(),
+            host_probes: HashMap::new(),
+            running_since: None,
+            open_ports: 0,
             port_results_selected: 0,
             input_selected: 0,
             progress: 0.0,
@@ -50,18 +95,42 @@ impl App {
             input_mode: false,
             target_input: String::new(),
             port_input: String::new(),
-            total_targets: 0,
-            complete_time: Arc::new(Mutex::new(Duration::from_millis(0)))
+            options_input: String::new(),
+            target_file_input: String::new(),
+            file_targets: Vec::new(),
+            seeds: HashMap::new(),
+            complete_time: Duration::ZERO,
+            rate: 0.0,
+            last_probes_sent: 0,
+            errors: Vec::new(),
         }
     }
 
+    /// Start with targets that were read before the interface came up, e.g. from stdin
+    pub fn with_targets(mut self, targets: Vec<String>) -> Self {
+        self.file_targets = targets;
+        self
+    }
+
+    /// Start with the inputs filled in, e.g. from the command line
+    pub fn with_inputs(mut self, targets: String, ports: String, options: String) -> Self {
+        self.target_input = targets;
+        self.port_input = ports;
+        self.options_input = options;
+        self
+    }
+
     pub fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
         let mut last_tick = Instant::now();
 
         loop {
-            if self.total_targets > 0 {
-                self.progress = self.state.lock().unwrap().len() as f32 / self.total_targets as f32;
-            }
+            let (total, done) = self.probe_totals();
+            self.progress = match total {
+                0 if !self.jobs.is_empty() && self.probes.is_empty() => 1.0,
+                // the targets are still to be expanded
+                0 => 0.0,
+                total => (done as f32 / total as f32).min(1.0),
+            };
                 
             terminal.draw(|frame| draw(frame, self))?;
             let timeout = TICK_TIME.saturating_sub(last_tick.elapsed());
@@ -70,25 +139,51 @@ impl App {
                 false => self.handle_events(timeout)?,
             }
             if self.ready_to_run {
-                self.target_input.split(" ").for_each(|s| self.targets.push(s.to_string()));
-                self.total_targets = self.targets.iter().map(|target| net::parse_cidr(target).expect("Invalid IP address").len()).sum();
-                self.ports = self.port_input.clone();
-                // clone data
-                let state = self.state.clone();
-                let targets = self.targets.clone();
-                let time = self.complete_time.clone();
-                let ports = self.ports.clone(); 
-                std::thread::spawn(move || {
-                    let rt = tokio::runtime::Runtime::new().unwrap();
-                    rt.block_on(async {
-                        net::execute_scan(state, targets, ports, time).await;
-                    });
-                });
                 self.ready_to_run = false;
+                self.ports = match scan::parse_ports_range(&self.port_input) {
+                    Ok(ports) => ports,
+                    Err(_) => {
+                        self.reopen_input(1);
+                        continue;
+                    }
+                };
+                let options = match ScanOptions::parse(&self.options_input) {
+                    Ok(options) if !options.reads_stdin() => options,
+                    _ => {
+                        self.reopen_input(2);
+                        continue;
+                    }
+                };
+                let mut targets: Vec<String> = self.target_input.split_whitespace().map(String::from).collect();
+                targets.extend(self.file_targets.iter().cloned());
+                let probes = ProbeCount {
+                    per_host: 1 + self.ports.tcp.len() as u64,
+                    total: 0,
+                    done: 0,
+                };
+                let scanner = Scanner::builder()
+                    .targets(targets)
+                    .ports(self.ports.clone())
+                    .options(options)
+                    .build();
+                let seed = scanner.seed();
+                let id = self.engine.submit(scanner);
+                // shown so the scan can be repeated with --seed
+                if let Some(seed) = seed {
+                    self.seeds.insert(id, seed);
+                }
+                self.jobs.insert(id, JobState::Queued);
+                self.probes.insert(id, probes);
+            }
+
+            while let Some(event) = self.engine.try_event() {
+                self.on_event(event);
             }
 
             if last_tick.elapsed() > TICK_TIME {
-                self.on_tick(); //TODO
+                let probes_sent = self.engine.probes_sent();
+                self.rate = (probes_sent - self.last_probes_sent) as f64 / last_tick.elapsed().as_secs_f64();
+                self.last_probes_sent = probes_sent;
                 last_tick = Instant::now();
             }
 
@@ -135,14 +230,10 @@ impl App {
                         }
                     }
                     FocusZone::PortList => {
-                        let target = self.targets.get(self.targets_selected);
-                        if target.is_some() {
-                            let port_results = self.port_results.get(target.unwrap());
-                            if port_results.is_some() {
-                                if self.port_results_selected > 0 {
-                                    self.port_results_selected -= 1;
-                                }
-                            }
+                        if let Some(ip) = self.hosts.get(self.targets_selected)
+                            && self.results.contains_key(ip)
+                            && self.port_results_selected > 0 {
+                            self.port_results_selected -= 1;
                         }
                     },
                     FocusZone::InputList => {
@@ -155,23 +246,19 @@ impl App {
             KeyCode::Down => {
                 match self.focus_zone {
                     FocusZone::AddressList => {
-                        if self.targets_selected + 1 < self.targets.len() {
+                        if self.targets_selected + 1 < self.hosts.len() {
                             self.targets_selected += 1;
                         }
                     }
                     FocusZone::PortList => {
-                        let target = self.targets.get(self.targets_selected);
-                        if target.is_some() {
-                            let port_results = self.port_results.get(target.unwrap());
-                            if port_results.is_some() {
-                                if self.port_results_selected + 1 < port_results.unwrap().len() {
-                                    self.port_results_selected += 1;
-                                }
-                            }
+                        if let Some(ip) = self.hosts.get(self.targets_selected)
+                            && let Some(host) = self.results.get(ip)
+                            && self.port_results_selected + 1 < host.ports.len() {
+                            self.port_results_selected += 1;
                         }
                     },
                     FocusZone::InputList => {
-                        if self.input_selected + 1 < 2 {
+                        if self.input_selected + 1 < 4 {
                             self.input_selected += 1;
                         }
                     },
@@ -184,6 +271,15 @@ impl App {
             KeyCode::Char('e') => {
                 self.ready_to_run = true;
             }
+            KeyCode::Char('c') => {
+                if let Some((&id, _)) = self.jobs.iter().find(|(_, state)| **state == JobState::Running) {
+                    self.engine.cancel(id);
+                }
+            }
+            KeyCode::Char('p') => match self.paused {
+                true => self.engine.resume(),
+                false => self.engine.pause(),
+            },
             _ => {}
         }
     }
@@ -196,11 +292,16 @@ impl App {
                     KeyCode::Enter => {
                         self.input_trigger = !self.input_trigger;
                         self.input_mode = false;
+                        if self.input_selected == 3 {
+                            self.load_target_file();
+                        }
                     },
                     KeyCode::Char(c) => {
                         match self.input_selected {
                             0 => self.target_input.push(c),
                             1 => self.port_input.push(c),
+                            2 => self.options_input.push(c),
+                            3 => self.target_file_input.push(c),
                             _ => {}
                         }
                     },
@@ -208,6 +309,8 @@ impl App {
                         match self.input_selected {
                             0 => self.target_input.pop(),
                             1 => self.port_input.pop(),
+                            2 => self.options_input.pop(),
+                            3 => self.target_file_input.pop(),
                             _ => Option::None
                         };
                     },
@@ -218,8 +321,170 @@ impl App {
         }
         Ok(())
     }
-    fn on_tick(&mut self) {
-        self.targets = self.state.lock().unwrap().keys().cloned().collect();
-        self.port_results = self.state.lock().unwrap().clone();
+    /// Load the targets of the Target File input, an empty input drops them
+    fn load_target_file(&mut self) {
+        if self.target_file_input.is_empty() {
+            self.file_targets.clear();
+            return;
+        }
+        // standard input is the terminal, or was already read for piped targets
+        if self.target_file_input == "-" {
+            self.file_targets.clear();
+            self.reopen_input(3);
+            return;
+        }
+        match target::read_target_file(Path::new(&self.target_file_input)) {
+            Ok(targets) => self.file_targets = targets,
+            Err(_) => {
+                self.file_targets.clear();
+                self.reopen_input(3);
+            }
+        }
+    }
+
+    /// Reopen an input so its popup shows why the value was rejected
+    fn reopen_input(&mut self, index: usize) {
+        self.focus_zone = FocusZone::InputList;
+        self.input_selected = index;
+        self.input_trigger = true;
+        self.input_mode = true;
+    }
+
+    fn on_event(&mut self, event: engine::Event) {
+        match event {
+            engine::Event::Queued(id) => {
+                self.jobs.insert(id, JobState::Queued);
+            }
+            engine::Event::Started(id) => {
+                self.jobs.insert(id, JobState::Running);
+                self.complete_time = Duration::ZERO;
+                self.running_since = Some(Instant::now());
+            }
+            engine::Event::Finished(id, elapsed) => {
+                self.complete_time = elapsed;
+                self.end_job(id, JobState::Finished);
+            }
+            engine::Event::Cancelled(id) => self.end_job(id, JobState::Cancelled),
+            engine::Event::Failed(id, error) => {
+                self.end_job(id, JobState::Failed);
+                self.show_error(format!("scan {} failed: {}", id, error));
+            }
+            engine::Event::Paused(paused) => self.paused = paused,
+            engine::Event::Scan(id, event) => self.on_scan_event(id, event),
+        }
+    }
+
+    /// Show results as they come in
+    fn on_scan_event(&mut self, id: JobId, event: ScanEvent) {
+        match event {
+            ScanEvent::Planned { hosts } => {
+                if let Some(probes) = self.probes.get_mut(&id) {
+                    probes.total = (hosts as u64).saturating_mul(probes.per_host);
+                }
+            }
+            ScanEvent::HostUp(host) => {
+                self.probe_done(id, host.ip());
+                self.list_host(host);
+            }
+            ScanEvent::Port { target, result } => {
+                self.probe_done(id, target.ip);
+                if let Some(host) = self.results.get_mut(&target.ip) {
+                    self.open_ports += open_count(std::slice::from_ref(&result));
+                    host.ports.push(result);
+                }
+            }
+            ScanEvent::HostDone(host) => {
+                // ports that were not probed, e.g. of a host that is down, are done as well
+                let counted = self.host_probes.remove(&host.ip()).unwrap_or(0);
+                if let Some(probes) = self.probes.get_mut(&id) {
+                    probes.done += probes.per_host.saturating_sub(counted);
+                }
+                self.list_host(host);
+            }
+            ScanEvent::Warning(warning) => self.show_error(warning.to_string()),
+        }
+    }
+
+    /// Add an error to the status area, once however often it happens
+    fn show_error(&mut self, error: String) {
+        if !self.errors.contains(&error) {
+            self.errors.push(error);
+        }
+    }
+
+    fn probe_done(&mut self, id: JobId, ip: IpAddr) {
+        *self.host_probes.entry(ip).or_default() += 1;
+        if let Some(probes) = self.probes.get_mut(&id) {
+            probes.done += 1;
+        }
+    }
+
+    /// A job will not complete more probes, whatever it was expected to send, and
+    /// no longer counts towards the progress
+    fn end_job(&mut self, id: JobId, state: JobState) {
+        self.probes.remove(&id);
+        // a queued job may be cancelled while another one runs
+        if self.jobs.insert(id, state) == Some(JobState::Running) {
+            self.host_probes.clear();
+            self.running_since = None;
+        }
+    }
+
+    /// Probes of the running and queued scans, and how many of them are completed
+    pub fn probe_totals(&self) -> (u64, u64) {
+        self.probes.values().fold((0, 0), |(total, done), probes| {
+            (total.saturating_add(probes.total), done + probes.done)
+        })
+    }
+
+    /// Time left until every scan of known size is done, at the pace of the running
+    /// scan. Queued scans count once they expanded their targets.
+    pub fn eta(&self) -> Option<Duration> {
+        let running = self.jobs.iter().find(|(_, state)| **state == JobState::Running)?;
+        let done = self.probes.get(running.0)?.done;
+        let (total, all_done) = self.probe_totals();
+        if done == 0 {
+            return None;
+        }
+        let elapsed = self.running_since?.elapsed();
+        Duration::try_from_secs_f64(elapsed.as_secs_f64() * total.saturating_sub(all_done) as f64 / done as f64).ok()
+    }
+
+    /// Add a host to the address list, or replace what is known about it,
+    /// e.g. once it is done or scanned again. Hosts that are down only count.
+    fn list_host(&mut self, host: HostResult) {
+        let ip = host.ip();
+        if host.status == HostStatus::Down {
+            self.down_hosts += 1;
+            // a host scanned again may have been up before
+            if let Some(old) = self.results.remove(&ip) {
+                self.open_ports -= open_count(&old.ports);
+                self.hosts.retain(|&listed| listed != ip);
+                self.targets_selected = self.targets_selected.min(self.hosts.len().saturating_sub(1));
+            }
+            return;
+        }
+        self.open_ports += open_count(&host.ports);
+        match self.results.insert(ip, host) {
+            Some(old) => self.open_ports -= open_count(&old.ports),
+            None => self.hosts.push(ip),
+        }
+    }
+
+    /// Seed of the running scan, or of the last one that ran while none does
+    pub fn seed(&self) -> Option<u64> {
+        let (id, _) = self.jobs.iter()
+            .find(|(_, state)| **state == JobState::Running)
+            .or_else(|| self.jobs.iter().rev().find(|(_, state)| **state != JobState::Queued))?;
+        self.seeds.get(id).copied()
+    }
+
+    /// Number of submitted scans in the given state
+    pub fn job_count(&self, state: JobState) -> usize {
+        self.jobs.values().filter(|&&job| job == state).count()
     }
 }
+
+fn open_count(results: &[ScanResult]) -> usize {
+    results.iter().filter(|result| result.state == PortState::Open).count()
+}
@@ -0,0 +1,405 @@
+use std::{collections::HashMap, error::Error, fmt::{self, Write}, fs, io, mem, net::IpAddr, path::Path, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use crate::{host::{HostReason, HostResult, HostStatus, MacAddr}, net::Target, scan::{PortState, Protocol, Reason, ScanResult}};
+
+/// Progress of a scan that can be saved and picked up again with `--resume`.
+/// Hosts are identified by their address, without the scope of a link-local
+/// address which the resuming scan fills in.
+///
+/// The file is plain text with one tab separated record per line: the scan it
+/// belongs to as `target <spec>` and `exclude <spec>` for every target and
+/// exclusion and `ports <ranges>`, then
+/// `port <address> <port>/<proto> <state> <reason> <retries>` for every probed port and
+/// `host <address> <status> <reason> <latency> <hostnames> <mac> <started> <finished>`
+/// for every finished host, appended as they complete.
+/// Latency is in microseconds, hostnames are comma separated, times are
+/// milliseconds since the Unix epoch and missing values are `-`.
+///
+/// Only what a resumed scan still has to take over and the records not written
+/// yet are kept in memory.
+#[derive(Default)]
+pub struct Checkpoint {
+    pub spec: ScanSpec,
+    /// Hosts the resumed scan finished, with all of their results
+    pub finished: HashMap<IpAddr, HostResult>,
+    /// Ports already probed on hosts the resumed scan did not finish
+    pub partial: HashMap<IpAddr, Vec<ScanResult>>,
+    /// Records of this scan still to be appended to the file
+    pending: String,
+}
+
+/// What a scan covers. A checkpoint only resumes the scan it was written by, as
+/// hosts and ports missing from it would otherwise count as done.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScanSpec {
+    /// Target specifications, including those of a target file
+    pub targets: Vec<String>,
+    pub exclude: Vec<String>,
+    /// TCP ports, sorted
+    pub ports: Vec<u16>,
+}
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(io::Error),
+    /// A line that is not a checkpoint record
+    InvalidRecord(usize),
+    /// The checkpoint was written by a scan of other targets, exclusions or ports
+    OtherScan(&'static str),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(e) => write!(f, "{}", e),
+            CheckpointError::InvalidRecord(line) => write!(f, "invalid checkpoint record on line {}", line),
+            CheckpointError::OtherScan(what) => {
+                write!(f, "written by a scan of other {}, resume with the same targets, exclusions and ports", what)
+            }
+        }
+    }
+}
+
+impl Error for CheckpointError {}
+
+impl From<io::Error> for CheckpointError {
+    fn from(e: io::Error) -> Self {
+        CheckpointError::Io(e)
+    }
+}
+
+impl Checkpoint {
+    /// Start the checkpoint of a scan
+    pub fn new(spec: ScanSpec) -> Self {
+        Self { spec, ..Self::default() }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, CheckpointError> {
+        let mut spec = ScanSpec::default();
+        let mut hosts = Vec::new();
+        let mut ports: HashMap<IpAddr, Vec<ScanResult>> = HashMap::new();
+        let mut contents = fs::read_to_string(path)?;
+        // a record cut short by a crash while it was appended
+        contents.truncate(contents.rfind('\n').map_or(0, |end| end + 1));
+        for (index, line) in contents.lines().enumerate() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            match fields[..] {
+                [""] => {}
+                ["target", target] => spec.targets.push(target.to_string()),
+                ["exclude", exclude] => spec.exclude.push(exclude.to_string()),
+                ["ports", ports] => {
+                    spec.ports = parse_ports(ports).ok_or(CheckpointError::InvalidRecord(index + 1))?;
+                }
+                ["host", address, ref host @ ..] => {
+                    let host = parse_host(address, host).ok_or(CheckpointError::InvalidRecord(index + 1))?;
+                    hosts.push(host);
+                }
+                ["port", address, port, state, reason, retries] => {
+                    let (address, result) = address.parse().ok().zip(parse_result(port, state, reason, retries))
+                        .ok_or(CheckpointError::InvalidRecord(index + 1))?;
+                    ports.entry(address).or_default().push(result);
+                }
+                _ => return Err(CheckpointError::InvalidRecord(index + 1)),
+            }
+        }
+        let mut checkpoint = Checkpoint::new(spec);
+        for mut host in hosts {
+            host.ports = ports.remove(&host.ip()).unwrap_or_default();
+            checkpoint.finished.insert(host.ip(), host);
+        }
+        checkpoint.partial = ports;
+        Ok(checkpoint)
+    }
+
+    /// Make sure the checkpoint belongs to the scan of `spec`
+    pub fn check_spec(&self, spec: &ScanSpec) -> Result<(), CheckpointError> {
+        if self.spec.targets != spec.targets {
+            return Err(CheckpointError::OtherScan("targets"));
+        }
+        if self.spec.exclude != spec.exclude {
+            return Err(CheckpointError::OtherScan("exclusions"));
+        }
+        if self.spec.ports != spec.ports {
+            return Err(CheckpointError::OtherScan("ports"));
+        }
+        Ok(())
+    }
+
+    pub fn port_done(&mut self, ip: IpAddr, result: &ScanResult) {
+        let _ = write_result(&mut self.pending, ip, result);
+    }
+
+    /// Record a finished host, its ports are recorded as they are probed
+    pub fn host_done(&mut self, host: &HostResult) {
+        let _ = write_host(&mut self.pending, host);
+    }
+
+    /// Take the records to append to the file
+    pub fn take_pending(&mut self) -> String {
+        mem::take(&mut self.pending)
+    }
+
+    /// Put back records that could not be written, ahead of newer ones
+    pub fn restore_pending(&mut self, records: &str) {
+        self.pending.insert_str(0, records);
+    }
+}
+
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for target in &self.spec.targets {
+            writeln!(f, "target\t{}", target)?;
+        }
+        for exclude in &self.spec.exclude {
+            writeln!(f, "exclude\t{}", exclude)?;
+        }
+        writeln!(f, "ports\t{}", format_ports(&self.spec.ports))?;
+        for host in self.finished.values() {
+            for result in &host.ports {
+                write_result(f, host.ip(), result)?;
+            }
+            write_host(f, host)?;
+        }
+        for (&address, results) in &self.partial {
+            for result in results {
+                write_result(f, address, result)?;
+            }
+        }
+        f.write_str(&self.pending)
+    }
+}
+
+fn write_host(f: &mut impl Write, host: &HostResult) -> fmt::Result {
+    writeln!(
+        f,
+        "host\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+        host.ip(),
+        host.status,
+        host.reason,
+        or_dash(host.latency.map(|latency| latency.as_micros())),
+        match host.hostnames.is_empty() {
+            true => String::from("-"),
+            false => host.hostnames.join(","),
+        },
+        or_dash(host.mac),
+        unix_millis(host.started),
+        or_dash(host.finished.map(unix_millis)),
+    )
+}
+
+fn write_result(f: &mut impl Write, address: IpAddr, result: &ScanResult) -> fmt::Result {
+    writeln!(f, "port\t{}\t{}/{}\t{}\t{}\t{}", address, result.port, result.protocol, result.state, result.reason, result.retries)
+}
+
+/// Sorted ports as ranges, e.g. `1-3,80`
+fn format_ports(ports: &[u16]) -> String {
+    let mut ranges: Vec<(u16, u16)> = Vec::new();
+    for &port in ports {
+        match ranges.last_mut() {
+            Some((_, end)) if port.checked_sub(1) == Some(*end) => *end = port,
+            _ => ranges.push((port, port)),
+        }
+    }
+    let ranges: Vec<String> = ranges.into_iter()
+        .map(|(start, end)| match start == end {
+            true => start.to_string(),
+            false => format!("{}-{}", start, end),
+        })
+        .collect();
+    ranges.join(",")
+}
+
+fn parse_ports(ranges: &str) -> Option<Vec<u16>> {
+    let mut ports = Vec::new();
+    for range in ranges.split(',').filter(|range| !range.is_empty()) {
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+        ports.extend(start.parse::<u16>().ok()?..=end.parse().ok()?);
+    }
+    Some(ports)
+}
+
+fn or_dash(value: Option<impl fmt::Display>) -> String {
+    value.map_or_else(|| String::from("-"), |value| value.to_string())
+}
+
+fn unix_millis(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis()
+}
+
+fn parse_time(millis: &str) -> Option<SystemTime> {
+    UNIX_EPOCH.checked_add(Duration::from_millis(millis.parse().ok()?))
+}
+
+/// A finished host from the fields after its address, without its ports
+fn parse_host(address: &str, fields: &[&str]) -> Option<HostResult> {
+    let [status, reason, latency, hostnames, mac, started, finished] = fields else {
+        return None;
+    };
+    let ip: IpAddr = address.parse().ok()?;
+    let mut host = HostResult::new(Target { ip, scope_id: 0 }, None);
+    host.status = match *status {
+        "up" => HostStatus::Up,
+        "down" => HostStatus::Down,
+        "unknown" => HostStatus::Unknown,
+        _ => return None,
+    };
+    host.reason = match *reason {
+        "echo-reply" => HostReason::EchoReply,
+        "no-response" => HostReason::NoResponse,
+        "user-set" => HostReason::UserSet,
+        "host-timeout" => HostReason::HostTimeout,
+        "error" => HostReason::LocalError,
+        _ => return None,
+    };
+    host.latency = match *latency {
+        "-" => None,
+        micros => Some(Duration::from_micros(micros.parse().ok()?)),
+    };
+    if *hostnames != "-" {
+        host.hostnames = hostnames.split(',').map(String::from).collect();
+    }
+    host.mac = match *mac {
+        "-" => None,
+        mac => Some(MacAddr::parse(mac)?),
+    };
+    host.started = parse_time(started)?;
+    host.finished = match *finished {
+        "-" => None,
+        finished => Some(parse_time(finished)?),
+    };
+    Some(host)
+}
+
+fn parse_result(port: &str, state: &str, reason: &str, retries: &str) -> Option<ScanResult> {
+    let (port, protocol) = port.split_once('/')?;
+    Some(ScanResult {
+        port: port.parse().ok()?,
+        protocol: match protocol {
+            "tcp" => Protocol::Tcp,
+            "udp" => Protocol::Udp,
+            _ => return None,
+        },
+        state: match state {
+            "open" => PortState::Open,
+            "closed" => PortState::Closed,
+            "filtered" => PortState::Filtered,
+            "error" => PortState::Error,
+            _ => return None,
+        },
+        reason: match reason {
+            "syn-ack" => Reason::SynAck,
+            "conn-refused" => Reason::ConnRefused,
+            "no-response" => Reason::NoResponse,
+            "host-unreach" => Reason::HostUnreach,
+            "net-unreach" => Reason::NetUnreach,
+            "error" => Reason::LocalError,
+            _ => return None,
+        },
+        retries: retries.parse().ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec() -> ScanSpec {
+        ScanSpec {
+            targets: vec![String::from("10.0.0.0/30"), String::from("fe80::1%1")],
+            exclude: vec![String::from("10.0.0.0")],
+            ports: vec![22, 80, 81, 82, 443],
+        }
+    }
+
+    fn result(port: u16, state: PortState, reason: Reason) -> ScanResult {
+        ScanResult { port, protocol: Protocol::Tcp, state, reason, retries: 1 }
+    }
+
+    fn load(contents: &str) -> Result<Checkpoint, CheckpointError> {
+        let path = std::env::temp_dir().join(format!("nmap-rs-checkpoint-{}-{:?}", std::process::id(), std::thread::current().id()));
+        fs::write(&path, contents).unwrap();
+        let loaded = Checkpoint::load(&path);
+        fs::remove_file(&path).unwrap();
+        loaded
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut checkpoint = Checkpoint::new(spec());
+        let mut file = checkpoint.to_string();
+        let mut host = HostResult::new(Target { ip: "10.0.0.1".parse().unwrap(), scope_id: 0 }, Some(String::from("gw.lan")));
+        host.set_status(HostStatus::Up, HostReason::EchoReply);
+        host.latency = Some(Duration::from_micros(1500));
+        host.mac = MacAddr::parse("00:1a:2b:3c:4d:5e");
+        host.ports = vec![result(22, PortState::Open, Reason::SynAck), result(80, PortState::Closed, Reason::ConnRefused)];
+        host.finish();
+        for result in &host.ports {
+            checkpoint.port_done(host.ip(), result);
+        }
+        checkpoint.host_done(&host);
+        let partial: IpAddr = "fe80::1".parse().unwrap();
+        checkpoint.port_done(partial, &result(443, PortState::Filtered, Reason::NoResponse));
+        file.push_str(&checkpoint.take_pending());
+        assert!(checkpoint.take_pending().is_empty());
+
+        // a resumed scan writes what it loaded to its own file first
+        let loaded = load(&load(&file).unwrap().to_string()).unwrap();
+        assert_eq!(loaded.spec, spec());
+        let restored = &loaded.finished[&host.ip()];
+        assert_eq!(restored.status, HostStatus::Up);
+        assert_eq!(restored.reason, HostReason::EchoReply);
+        assert_eq!(restored.latency, host.latency);
+        assert_eq!(restored.hostnames, host.hostnames);
+        assert_eq!(restored.mac, host.mac);
+        assert_eq!(restored.ports, host.ports);
+        assert_eq!(unix_millis(restored.started), unix_millis(host.started));
+        assert_eq!(restored.finished.map(unix_millis), host.finished.map(unix_millis));
+        assert_eq!(loaded.partial.len(), 1);
+        assert_eq!(loaded.partial[&partial], [result(443, PortState::Filtered, Reason::NoResponse)]);
+    }
+
+    #[test]
+    fn unwritten_records_are_kept_in_order() {
+        let mut checkpoint = Checkpoint::new(spec());
+        checkpoint.port_done("10.0.0.1".parse().unwrap(), &result(22, PortState::Open, Reason::SynAck));
+        let failed = checkpoint.take_pending();
+        checkpoint.port_done("10.0.0.1".parse().unwrap(), &result(80, PortState::Open, Reason::SynAck));
+        checkpoint.restore_pending(&failed);
+        let loaded = load(&checkpoint.to_string()).unwrap();
+        let ports: Vec<u16> = loaded.partial[&"10.0.0.1".parse().unwrap()].iter().map(|result| result.port).collect();
+        assert_eq!(ports, [22, 80]);
+    }
+
+    #[test]
+    fn invalid_records() {
+        let loaded = load("ports\t22\nport\t10.0.0.1\t22/sctp\topen\tsyn-ack\t0\n");
+        assert!(matches!(loaded, Err(CheckpointError::InvalidRecord(2))));
+    }
+
+    #[test]
+    fn records_cut_short_are_dropped() {
+        let loaded = load("ports\t22\nport\t10.0.0.1\t22/tcp\topen\tsyn-ack\t0\nport\t10.0.0.1\t2").unwrap();
+        assert_eq!(loaded.partial[&"10.0.0.1".parse().unwrap()].len(), 1);
+    }
+
+    #[test]
+    fn other_scans_are_rejected() {
+        let checkpoint = Checkpoint::new(spec());
+        assert!(checkpoint.check_spec(&spec()).is_ok());
+        let other = ScanSpec { ports: vec![22], ..spec() };
+        assert!(matches!(checkpoint.check_spec(&other), Err(CheckpointError::OtherScan("ports"))));
+        let other = ScanSpec { exclude: Vec::new(), ..spec() };
+        assert!(matches!(checkpoint.check_spec(&other), Err(CheckpointError::OtherScan("exclusions"))));
+        let other = ScanSpec { targets: vec![String::from("10.0.0.0/30")], ..spec() };
+        assert!(matches!(checkpoint.check_spec(&other), Err(CheckpointError::OtherScan("targets"))));
+    }
+
+    #[test]
+    fn port_ranges() {
+        assert_eq!(format_ports(&[1, 2, 3, 80, 443, 444, 65535]), "1-3,80,443-444,65535");
+        assert_eq!(format_ports(&[]), "");
+        assert_eq!(parse_ports("1-3,80,443-444,65535"), Some(vec![1, 2, 3, 80, 443, 444, 65535]));
+        assert_eq!(parse_ports(""), Some(Vec::new()));
+        assert_eq!(parse_ports("1-x"), None);
+    }
+}
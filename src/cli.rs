@@ -0,0 +1,258 @@
+use std::{collections::HashSet, error::Error, fmt, fs::File, io::Write, path::PathBuf};
+
+use nmap_rs::{host::{HostResult, HostStatus}, net::ScanEvent, options::{OptionsError, ScanOptions}, scan::{self, PortList, PortSpecError, PortState, ScanResult}, services, Scanner};
+
+pub const USAGE: &str = "\
+Usage: nmap-rs [options] <targets>
+       nmap-rs [--tui] [options] [targets]
+
+Without arguments, or with --tui, the interactive interface is started with
+the given targets, ports and options filled in.
+
+  -p <ports>          ports to scan, e.g. 22,80,8000-8100 or top:100 (default top:1000)
+  --top-ports <n>     scan the n most common ports
+  -T<0-5>             timing template, individual timing options override it
+  -oN <file>          also write the results to a file
+  --open              only show open ports
+  -v, --verbose       also report hosts that are down or were not finished
+  --tui               start the interactive interface
+  -h, --help          show this help
+
+Every other option of the interface's Options field is accepted as well,
+e.g. -Pn, -R, -iL <file>, --exclude <targets>, --max-rate <n>, --resume <file>.";
+
+/// Command line of a headless scan, or of the interface to fill in
+#[derive(Default)]
+pub struct Cli {
+    pub tui: bool,
+    pub help: bool,
+    pub targets: Vec<String>,
+    /// Port specification as given
+    pub ports: Option<String>,
+    /// Flags for the scan options as given
+    pub options: Vec<String>,
+    pub normal_output: Option<PathBuf>,
+    pub open_only: bool,
+    pub verbose: bool,
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    MissingValue(String),
+    Options(OptionsError),
+    Ports(PortSpecError),
+    NoTargets,
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::MissingValue(option) => write!(f, "{} needs a value", option),
+            CliError::Options(e) => write!(f, "{}", e),
+            CliError::Ports(e) => write!(f, "invalid port specification: {}", e),
+            CliError::NoTargets => write!(f, "no targets given"),
+        }
+    }
+}
+
+impl Error for CliError {}
+
+impl Cli {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, CliError> {
+        let mut cli = Cli::default();
+        let mut rest = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--tui" => cli.tui = true,
+                "-h" | "--help" => cli.help = true,
+                "-p" => cli.ports = Some(value(&arg, args.next())?),
+                "--top-ports" => cli.ports = Some(format!("top:{}", value(&arg, args.next())?)),
+                "-oN" => cli.normal_output = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--open" => cli.open_only = true,
+                "-v" | "--verbose" => cli.verbose = true,
+                _ => match arg.strip_prefix("-p") {
+                    Some(ports) => cli.ports = Some(ports.to_string()),
+                    None => rest.push(arg),
+                },
+            }
+        }
+        // whatever the scan options do not take is a target
+        let operands: HashSet<usize> = ScanOptions::default()
+            .apply_args(rest.iter().map(String::as_str))
+            .map_err(CliError::Options)?
+            .into_iter()
+            .collect();
+        for (index, arg) in rest.into_iter().enumerate() {
+            match operands.contains(&index) {
+                true => cli.targets.push(arg),
+                false => cli.options.push(arg),
+            }
+        }
+        if let Some(ports) = &cli.ports {
+            scan::parse_ports_range(ports).map_err(CliError::Ports)?;
+        }
+        Ok(cli)
+    }
+
+    /// The scan the command line describes
+    pub fn scanner(&self) -> Result<Scanner, CliError> {
+        let options = ScanOptions::parse(&self.options.join(" ")).map_err(CliError::Options)?;
+        if self.targets.is_empty() && options.input_file.is_none() {
+            return Err(CliError::NoTargets);
+        }
+        let ports = match &self.ports {
+            Some(ports) => scan::parse_ports_range(ports),
+            None => scan::top_ports(1000).map(|tcp| PortList { tcp, udp: Vec::new() }),
+        };
+        Ok(Scanner::builder()
+            .targets(self.targets.iter().cloned())
+            .ports(ports.map_err(CliError::Ports)?)
+            .options(options)
+            .build())
+    }
+}
+
+fn value(option: &str, value: Option<String>) -> Result<String, CliError> {
+    value.ok_or_else(|| CliError::MissingValue(option.to_string()))
+}
+
+/// Run the scan without the interface, printing every host as soon as it is done
+/// and every distinct warning to stderr
+pub fn run(cli: &Cli, scanner: Scanner) -> Result<(), Box<dyn Error>> {
+    let mut file = match &cli.normal_output {
+        Some(path) => Some(File::create(path)?),
+        None => None,
+    };
+    let seed = scanner.seed();
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let mut scan = scanner.spawn();
+        let mut up = 0;
+        let mut hosts = 0;
+        let mut warnings = HashSet::new();
+        while let Some(event) = scan.next_event().await {
+            match event {
+                ScanEvent::HostDone(host) => {
+                    hosts += 1;
+                    if host.status == HostStatus::Up {
+                        up += 1;
+                    }
+                    // like nmap, only hosts that are up are listed unless asked for
+                    if host.status != HostStatus::Up && !cli.verbose {
+                        continue;
+                    }
+                    let report = host_report(&host, cli.open_only);
+                    print!("{}", report);
+                    if let Some(file) = &mut file {
+                        file.write_all(report.as_bytes())?;
+                    }
+                }
+                ScanEvent::Planned { .. } | ScanEvent::HostUp(_) | ScanEvent::Port { .. } => {}
+                ScanEvent::Warning(warning) => {
+                    let warning = warning.to_string();
+                    if !warnings.contains(&warning) {
+                        eprintln!("Warning: {}", warning);
+                        warnings.insert(warning);
+                    }
+                }
+            }
+        }
+        let elapsed = scan.finish().await?;
+        let mut summary = format!("nmap-rs done: {} hosts ({} up) scanned in {:.2} seconds", hosts, up, elapsed.as_secs_f64());
+        if let Some(seed) = seed {
+            summary.push_str(&format!(" (seed {})", seed));
+        }
+        summary.push('\n');
+        print!("{}", summary);
+        if let Some(file) = &mut file {
+            file.write_all(summary.as_bytes())?;
+        }
+        Ok(())
+    })
+}
+
+/// Results of a host in nmap's normal output format
+fn host_report(host: &HostResult, open_only: bool) -> String {
+    let mut shown: Vec<&ScanResult> = host.ports.iter()
+        .filter(|result| !open_only || result.state == PortState::Open)
+        .collect();
+    shown.sort_by_key(|result| result.port);
+    if shown.is_empty() && open_only {
+        return String::new();
+    }
+    let mut report = format!("Scan report for {}\nHost is {} ({}", host, host.status, host.reason);
+    if let Some(latency) = host.latency {
+        report.push_str(&format!(", {:.4}s latency", latency.as_secs_f64()));
+    }
+    report.push_str(").\n");
+    if let Some(mac) = host.mac {
+        report.push_str(&format!("MAC Address: {}\n", mac));
+    }
+    if shown.is_empty() {
+        report.push('\n');
+        return report;
+    }
+    report.push_str(&format!("{:<10}{:<10}{:<16}REASON\n", "PORT", "STATE", "SERVICE"));
+    for result in shown {
+        report.push_str(&format!(
+            "{:<10}{:<10}{:<16}{}\n",
+            format!("{}/{}", result.port, result.protocol),
+            result.state.to_string(),
+            services::service_name(result.port, result.protocol).unwrap_or("unknown"),
+            result.reason,
+        ));
+    }
+    report.push('\n');
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Cli, CliError> {
+        Cli::parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn targets_and_options() {
+        let cli = parse("-Pn 10.0.0.0/24 --max-rate 100 -T4 host.lan --exclude 10.0.0.1").unwrap();
+        assert_eq!(cli.targets, ["10.0.0.0/24", "host.lan"]);
+        assert_eq!(cli.options, ["-Pn", "--max-rate", "100", "-T4", "--exclude", "10.0.0.1"]);
+        assert!(!cli.tui && !cli.help && !cli.open_only && !cli.verbose);
+    }
+
+    #[test]
+    fn option_values_equal_to_targets() {
+        let cli = parse("--exclude 127.0.0.1 -Pn 127.0.0.1 -p 80").unwrap();
+        assert_eq!(cli.targets, ["127.0.0.1"]);
+        assert_eq!(cli.options, ["--exclude", "127.0.0.1", "-Pn"]);
+        assert!(cli.scanner().is_ok());
+    }
+
+    #[test]
+    fn own_flags() {
+        let cli = parse("--tui -v --open -oN out.txt -h 10.0.0.1").unwrap();
+        assert!(cli.tui && cli.verbose && cli.open_only && cli.help);
+        assert_eq!(cli.normal_output, Some(PathBuf::from("out.txt")));
+        assert_eq!(cli.targets, ["10.0.0.1"]);
+        assert!(cli.options.is_empty());
+    }
+
+    #[test]
+    fn ports() {
+        assert_eq!(parse("-p 22,80 10.0.0.1").unwrap().ports.as_deref(), Some("22,80"));
+        assert_eq!(parse("-p- 10.0.0.1").unwrap().ports.as_deref(), Some("-"));
+        assert_eq!(parse("--top-ports 10 10.0.0.1").unwrap().ports.as_deref(), Some("top:10"));
+        assert!(matches!(parse("-p 70000 10.0.0.1"), Err(CliError::Ports(PortSpecError::InvalidPort(_)))));
+        assert!(matches!(parse("10.0.0.1 -p"), Err(CliError::MissingValue(option)) if option == "-p"));
+    }
+
+    #[test]
+    fn invalid_command_lines() {
+        assert!(matches!(parse("--bogus 10.0.0.1"), Err(CliError::Options(_))));
+        assert!(matches!(parse("-Pn").unwrap().scanner(), Err(CliError::NoTargets)));
+        assert!(parse("-iL targets.txt").unwrap().scanner().is_ok());
+    }
+}
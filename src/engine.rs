@@ -0,0 +1,189 @@
+use std::{collections::VecDeque, sync::{atomic::{AtomicU64, Ordering}, mpsc, Arc}, thread, time::Duration};
+
+use tokio::{sync::{mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender}, watch}, task::{JoinError, JoinHandle}};
+
+use crate::{error::ScanError, net::ScanEvent, Scanner};
+
+pub type JobId = u64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobState {
+    Queued,
+    Running,
+    Finished,
+    Cancelled,
+    /// The scan could not run, e.g. because of an invalid target
+    Failed,
+}
+
+pub enum Command {
+    Submit(JobId, Box<Scanner>),
+    /// Drop a queued job or abort the running one
+    Cancel(JobId),
+    /// Hold or continue sending probes
+    Pause,
+    Resume,
+}
+
+/// What the engine reports back about its jobs
+pub enum Event {
+    Queued(JobId),
+    Started(JobId),
+    /// The job is done, with the time it took
+    Finished(JobId, Duration),
+    Cancelled(JobId),
+    Failed(JobId, ScanError),
+    /// Probe dispatch was paused or resumed
+    Paused(bool),
+    /// Progress of the running job
+    Scan(JobId, ScanEvent),
+}
+
+/// Long-lived scan service owning one tokio runtime. Jobs are queued through
+/// commands and run one after another, reporting their progress as events.
+pub struct Engine {
+    commands: Option<UnboundedSender<Command>>,
+    events: mpsc::Receiver<Event>,
+    probes_sent: Arc<AtomicU64>,
+    next_id: JobId,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Engine {
+    pub fn start() -> std::io::Result<Self> {
+        let runtime = tokio::runtime::Runtime::new()?;
+        let (commands, command_rx) = unbounded_channel();
+        let (event_tx, events) = mpsc::channel();
+        let probes_sent = Arc::new(AtomicU64::new(0));
+        let probes = probes_sent.clone();
+        let thread = thread::Builder::new().name(String::from("scan-engine")).spawn(move || {
+            runtime.block_on(run(command_rx, event_tx, probes));
+            // do not wait for blocking lookups of an abandoned scan
+            runtime.shutdown_background();
+        })?;
+        Ok(Self {
+            commands: Some(commands),
+            events,
+            probes_sent,
+            next_id: 1,
+            thread: Some(thread),
+        })
+    }
+
+    /// Queue a scan, it starts once the jobs before it are finished
+    pub fn submit(&mut self, scanner: Scanner) -> JobId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(Command::Submit(id, Box::new(scanner)));
+        id
+    }
+
+    pub fn cancel(&self, id: JobId) {
+        self.send(Command::Cancel(id));
+    }
+
+    pub fn pause(&self) {
+        self.send(Command::Pause);
+    }
+
+    pub fn resume(&self) {
+        self.send(Command::Resume);
+    }
+
+    fn send(&self, command: Command) {
+        // the engine thread only stops once the commands are closed on drop
+        if let Some(commands) = &self.commands {
+            let _ = commands.send(command);
+        }
+    }
+
+    /// Next event of the engine, without waiting
+    pub fn try_event(&self) -> Option<Event> {
+        self.events.try_recv().ok()
+    }
+
+    /// Probes sent by every job so far
+    pub fn probes_sent(&self) -> u64 {
+        self.probes_sent.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        // closing the command channel stops the engine and aborts the running job
+        self.commands.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn panic_message(error: JoinError) -> String {
+    match error.try_into_panic() {
+        Ok(panic) => match panic.downcast::<String>() {
+            Ok(message) => *message,
+            Err(panic) => panic.downcast_ref::<&str>().map_or_else(|| String::from("unknown panic"), |message| message.to_string()),
+        },
+        Err(error) => error.to_string(),
+    }
+}
+
+async fn run(mut commands: UnboundedReceiver<Command>, events: mpsc::Sender<Event>, probes: Arc<AtomicU64>) {
+    let mut queue: VecDeque<(JobId, Box<Scanner>)> = VecDeque::new();
+    let mut running: Option<(JobId, JoinHandle<Result<Duration, ScanError>>)> = None;
+    let (pause, paused) = watch::channel(false);
+    loop {
+        if running.is_none()
+            && let Some((id, scanner)) = queue.pop_front() {
+            let report = events.clone();
+            let report = Arc::new(move |event| {
+                let _ = report.send(Event::Scan(id, event));
+            });
+            let scan = scanner.execute(report, probes.clone(), paused.clone());
+            running = Some((id, tokio::spawn(scan)));
+            let _ = events.send(Event::Started(id));
+        }
+        tokio::select! {
+            command = commands.recv() => match command {
+                Some(Command::Submit(id, scanner)) => {
+                    queue.push_back((id, scanner));
+                    let _ = events.send(Event::Queued(id));
+                }
+                Some(Command::Cancel(id)) => {
+                    if let Some((running_id, handle)) = running.take_if(|(running_id, _)| *running_id == id) {
+                        // dropping the scan aborts its hosts and their probes, wait for
+                        // that so they are gone before the next job starts
+                        handle.abort();
+                        let _ = handle.await;
+                        let _ = events.send(Event::Cancelled(running_id));
+                    } else if let Some(index) = queue.iter().position(|(queued_id, _)| *queued_id == id) {
+                        queue.remove(index);
+                        let _ = events.send(Event::Cancelled(id));
+                    }
+                }
+                Some(Command::Pause) => {
+                    pause.send_replace(true);
+                    let _ = events.send(Event::Paused(true));
+                }
+                Some(Command::Resume) => {
+                    pause.send_replace(false);
+                    let _ = events.send(Event::Paused(false));
+                }
+                None => break,
+            },
+            outcome = async { (&mut running.as_mut().unwrap().1).await }, if running.is_some() => {
+                let (id, _) = running.take().unwrap();
+                // a cancelled job is aborted by the Cancel command, so this one panicked
+                let event = match outcome {
+                    Ok(Ok(elapsed)) => Event::Finished(id, elapsed),
+                    Ok(Err(error)) => Event::Failed(id, error),
+                    Err(error) => Event::Failed(id, ScanError::Panicked(panic_message(error))),
+                };
+                let _ = events.send(event);
+            }
+        }
+    }
+    if let Some((_, handle)) = running {
+        handle.abort();
+    }
+}
@@ -0,0 +1,124 @@
+use std::{error::Error, fmt, io, path::PathBuf};
+
+use crate::{checkpoint::CheckpointError, scan::PortSpecError, target::TargetSpecError};
+
+/// Everything that can go wrong while scanning. Errors that end a scan are
+/// returned by it, the others are reported as the scan goes on.
+#[derive(Debug)]
+pub enum ScanError {
+    /// A target or exclusion that cannot be scanned
+    Target(TargetError),
+    /// Ports that cannot be scanned
+    Ports(PortSpecError),
+    /// The system ran out of sockets, file descriptors, buffers or memory,
+    /// usually because too many probes are in flight
+    ResourceExhausted(io::Error),
+    /// The system refused, e.g. ICMP sockets to an unprivileged user
+    Permission(io::Error),
+    /// Sending or receiving a probe failed
+    Network(io::Error),
+    /// The checkpoint could not be read or written
+    Checkpoint(CheckpointError),
+    /// The scan sent fewer probes per second than `--min-rate` asked for
+    MinRateMissed { min_rate: f64, rate: f64 },
+    /// The scan crashed, with the panic message
+    Panicked(String),
+    /// The scan was cancelled before it was done
+    Cancelled,
+}
+
+#[derive(Debug)]
+pub enum TargetError {
+    /// A target or exclusion specification that does not parse
+    Parse(TargetSpecError),
+    /// A hostname without addresses
+    Resolve { name: String, error: io::Error },
+    /// A target file that cannot be read
+    File { path: PathBuf, error: io::Error },
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScanError::Target(e) => write!(f, "{}", e),
+            ScanError::Ports(e) => write!(f, "{}", e),
+            ScanError::ResourceExhausted(e) => write!(f, "out of resources: {}", e),
+            ScanError::Permission(e) => write!(f, "permission denied: {}", e),
+            ScanError::Network(e) => write!(f, "network error: {}", e),
+            ScanError::Checkpoint(e) => write!(f, "checkpoint: {}", e),
+            ScanError::MinRateMissed { min_rate, rate } => {
+                write!(f, "sent {:.1} probes/s, below --min-rate {}", rate, min_rate)
+            }
+            ScanError::Panicked(message) => write!(f, "scan crashed: {}", message),
+            ScanError::Cancelled => write!(f, "scan cancelled"),
+        }
+    }
+}
+
+impl Error for ScanError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ScanError::Target(e) => Some(e),
+            ScanError::Ports(e) => Some(e),
+            ScanError::ResourceExhausted(e) | ScanError::Permission(e) | ScanError::Network(e) => Some(e),
+            ScanError::Checkpoint(e) => Some(e),
+            ScanError::MinRateMissed { .. } | ScanError::Panicked(_) | ScanError::Cancelled => None,
+        }
+    }
+}
+
+impl fmt::Display for TargetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TargetError::Parse(e) => write!(f, "{}", e),
+            TargetError::Resolve { name, error } => write!(f, "failed to resolve {}: {}", name, error),
+            TargetError::File { path, error } => write!(f, "failed to read {}: {}", path.display(), error),
+        }
+    }
+}
+
+impl Error for TargetError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TargetError::Parse(e) => Some(e),
+            TargetError::Resolve { error, .. } | TargetError::File { error, .. } => Some(error),
+        }
+    }
+}
+
+impl From<TargetError> for ScanError {
+    fn from(e: TargetError) -> Self {
+        ScanError::Target(e)
+    }
+}
+
+impl From<TargetSpecError> for ScanError {
+    fn from(e: TargetSpecError) -> Self {
+        ScanError::Target(TargetError::Parse(e))
+    }
+}
+
+impl From<PortSpecError> for ScanError {
+    fn from(e: PortSpecError) -> Self {
+        ScanError::Ports(e)
+    }
+}
+
+impl From<CheckpointError> for ScanError {
+    fn from(e: CheckpointError) -> Self {
+        ScanError::Checkpoint(e)
+    }
+}
+
+/// Sort a system error into resource exhaustion, permission or network trouble
+impl From<io::Error> for ScanError {
+    fn from(e: io::Error) -> Self {
+        match e.raw_os_error() {
+            Some(libc::EMFILE | libc::ENFILE | libc::ENOBUFS | libc::ENOMEM | libc::EADDRNOTAVAIL) => {
+                ScanError::ResourceExhausted(e)
+            }
+            _ if e.kind() == io::ErrorKind::PermissionDenied => ScanError::Permission(e),
+            _ => ScanError::Network(e),
+        }
+    }
+}
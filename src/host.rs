@@ -0,0 +1,159 @@
+use std::{fmt, net::IpAddr, time::{Duration, SystemTime}};
+
+use crate::{net::Target, scan::ScanResult};
+
+/// Everything a scan found out about a single host. Hosts are told apart by
+/// their address, e.g. results are kept in maps keyed by `target.ip`.
+#[derive(Clone, Debug)]
+pub struct HostResult {
+    pub target: Target,
+    pub status: HostStatus,
+    /// Why the host is considered up or down
+    pub reason: HostReason,
+    /// Smoothed round trip time of the answers of the host
+    pub latency: Option<Duration>,
+    /// Names the host was given as, or found under by a reverse lookup
+    pub hostnames: Vec<String>,
+    /// Hardware address, only known for hosts on a local IPv4 network
+    pub mac: Option<MacAddr>,
+    pub ports: Vec<ScanResult>,
+    /// When the scan of the host started
+    pub started: SystemTime,
+    /// When the scan of the host ended, `None` while it is still scanned
+    pub finished: Option<SystemTime>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HostStatus {
+    Up,
+    Down,
+    /// Scanning the host was not finished, e.g. it timed out or could not be pinged
+    Unknown,
+}
+
+/// Why a host has its status, following nmap's terminology
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HostReason {
+    /// The host answered the discovery ping
+    EchoReply,
+    /// The discovery ping went unanswered
+    NoResponse,
+    /// Discovery was skipped (`-Pn`), the host is assumed to be up
+    UserSet,
+    /// The scan of the host took longer than `--host-timeout`
+    HostTimeout,
+    /// The discovery ping could not be sent
+    LocalError,
+}
+
+/// An Ethernet hardware address, e.g. `00:1a:2b:3c:4d:5e`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MacAddr(pub [u8; 6]);
+
+impl HostResult {
+    /// A host the scan starts on, with nothing known about it yet
+    pub fn new(target: Target, hostname: Option<String>) -> Self {
+        Self {
+            target,
+            status: HostStatus::Unknown,
+            reason: HostReason::NoResponse,
+            latency: None,
+            hostnames: hostname.into_iter().collect(),
+            mac: None,
+            ports: Vec::new(),
+            started: SystemTime::now(),
+            finished: None,
+        }
+    }
+
+    pub fn ip(&self) -> IpAddr {
+        self.target.ip
+    }
+
+    /// Set the status of the host together with the reason for it
+    pub fn set_status(&mut self, status: HostStatus, reason: HostReason) {
+        self.status = status;
+        self.reason = reason;
+    }
+
+    /// Mark the scan of the host as ended
+    pub fn finish(&mut self) {
+        self.finished = Some(SystemTime::now());
+    }
+
+    /// Time the scan of the host took so far
+    pub fn elapsed(&self) -> Duration {
+        let end = self.finished.unwrap_or_else(SystemTime::now);
+        end.duration_since(self.started).unwrap_or_default()
+    }
+}
+
+/// The address followed by the first name of the host, e.g. `10.0.0.5 (nas.lan)`
+impl fmt::Display for HostResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.hostnames.first() {
+            Some(hostname) => write!(f, "{} ({})", self.target, hostname),
+            None => write!(f, "{}", self.target),
+        }
+    }
+}
+
+impl fmt::Display for HostStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HostStatus::Up => write!(f, "up"),
+            HostStatus::Down => write!(f, "down"),
+            HostStatus::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+impl fmt::Display for HostReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HostReason::EchoReply => write!(f, "echo-reply"),
+            HostReason::NoResponse => write!(f, "no-response"),
+            HostReason::UserSet => write!(f, "user-set"),
+            HostReason::HostTimeout => write!(f, "host-timeout"),
+            HostReason::LocalError => write!(f, "error"),
+        }
+    }
+}
+
+impl MacAddr {
+    /// Parse colon separated hex octets, e.g. `00:1a:2b:3c:4d:5e`
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut octets = [0; 6];
+        let mut parts = text.split(':');
+        for octet in &mut octets {
+            *octet = u8::from_str_radix(parts.next()?, 16).ok()?;
+        }
+        match parts.next() {
+            Some(_) => None,
+            None => Some(MacAddr(octets)),
+        }
+    }
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", a, b, c, d, e, g)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mac_addresses() {
+        let mac = MacAddr::parse("00:1A:2b:3c:4d:5e").unwrap();
+        assert_eq!(mac, MacAddr([0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e]));
+        assert_eq!(mac.to_string(), "00:1a:2b:3c:4d:5e");
+        assert_eq!(MacAddr::parse("00:1a:2b:3c:4d"), None);
+        assert_eq!(MacAddr::parse("00:1a:2b:3c:4d:5e:6f"), None);
+        assert_eq!(MacAddr::parse("00:1a:2b:3c:4d:zz"), None);
+        assert_eq!(MacAddr::parse(""), None);
+    }
+}
@@ -0,0 +1,19 @@
+//! Port scanner with nmap style target, port and timing specifications.
+//! [`Scanner`] runs a single scan, [`engine::Engine`] queues scans on a
+//! runtime of its own for long running front ends.
+//!
+//! The command line program needs the default `tui` feature, depend on the
+//! library with `default-features = false` to leave out its terminal interface.
+pub mod checkpoint;
+pub mod engine;
+pub mod error;
+pub mod host;
+pub mod net;
+pub mod options;
+pub mod scan;
+pub mod scanner;
+pub mod services;
+pub mod target;
+pub mod timing;
+
+pub use scanner::Scanner;
@@ -1,23 +1,52 @@
 use app::App;
-use std::{io, sync::{Arc, Mutex}};
-use net::Results;
+use cli::Cli;
+use std::{env, io::{self, IsTerminal}, process};
+use nmap_rs::{engine::Engine, target};
 mod app;
+mod cli;
 mod ui;
-mod scan;
-mod net;
-
-#[derive(Default)]
-struct Progress {
-    total: usize,
-    finished: usize,
-}
 
 fn main() -> io::Result<()> {
-    
-    let shared_state = Arc::new(Mutex::new(Results::new()));
+    let args: Vec<String> = env::args().skip(1).collect();
+    let interactive = args.is_empty();
+    let cli = match Cli::parse(args) {
+        Ok(cli) => cli,
+        Err(e) => usage_error(e),
+    };
+    if cli.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+    if !interactive && !cli.tui {
+        let scanner = match cli.scanner() {
+            Ok(scanner) => scanner,
+            Err(e) => usage_error(e),
+        };
+        if let Err(e) = cli::run(&cli, scanner) {
+            eprintln!("nmap-rs: {}", e);
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // a target list piped into the program, the interface itself reads keys from the tty
+    let targets = if io::stdin().is_terminal() {
+        Vec::new()
+    } else {
+        target::read_specs(io::stdin().lock())?
+    };
 
+    let engine = Engine::start()?;
     let mut terminal = ratatui::init();
-    let app_result = App::init(shared_state.clone()).run(&mut terminal);
+    let app_result = App::init(engine)
+        .with_targets(targets)
+        .with_inputs(cli.targets.join(" "), cli.ports.unwrap_or_default(), cli.options.join(" "))
+        .run(&mut terminal);
     ratatui::restore();
     app_result
 }
+
+fn usage_error(error: cli::CliError) -> ! {
+    eprintln!("nmap-rs: {}\n\n{}", error, cli::USAGE);
+    process::exit(2)
+}
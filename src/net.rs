@@ -1,88 +1,568 @@
-use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Duration, Instant}};
+use std::{collections::{HashMap, HashSet}, ffi::CStr, fmt, fs, io::{self, Write}, mem, net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV6}, path::{Path, PathBuf}, pin::pin, ptr, sync::{atomic::AtomicU64, Arc, Mutex}, time::{Duration, Instant}};
 
-use crate::scan::{ self, ScanResult};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use surge_ping::{Client, Config, PingIdentifier, PingSequence, SurgeError, ICMP};
+use tokio::{io::AsyncWriteExt, sync::watch, task::JoinSet};
 
+use crate::{checkpoint::{Checkpoint, CheckpointError, ScanSpec}, error::{ScanError, TargetError}, host::{HostReason, HostResult, HostStatus, MacAddr}, options::ScanOptions, scan::{ self, Limiter, PortList, PortSpecError, ScanResult, Technique}, target::{self, TargetList, TargetSpec}, timing::{RttEstimator, Timing}};
 
+/// Progress of a scan, reported the moment it happens
+pub enum ScanEvent {
+    /// Targets are expanded, with the number of hosts the scan covers
+    Planned { hosts: usize },
+    /// The host is up, its ports are scanned next
+    HostUp(HostResult),
+    /// The state of a port of a live host is known
+    Port { target: Target, result: ScanResult },
+    /// Nothing more to do for the host, with all of its results
+    HostDone(HostResult),
+    /// Something went wrong, but the scan goes on
+    Warning(ScanError),
+}
+
+/// How hosts are found to be up before their ports are scanned
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Discovery {
+    /// Send an ICMP echo request and only scan hosts that answer
+    #[default]
+    Ping,
+    /// Treat every host as up (`-Pn`)
+    Skip,
+}
+
+/// Receiver of the events of a scan
+pub type Report = Arc<dyn Fn(ScanEvent) + Send + Sync>;
 
-pub type Results = HashMap<String, Vec<ScanResult>>;
+/// Time between two appends to the checkpoint
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Number of consecutive hosts shuffled together when randomizing the host order
+const HOST_SHUFFLE_BLOCK: usize = 16384;
+
+/// A single address to probe. IPv6 link-local addresses also carry the scope
+/// (interface index) they are reachable through, e.g. `fe80::1%eth0`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Target {
+    pub ip: IpAddr,
+    pub scope_id: u32,
+}
 
-pub async fn execute_scan(state: Arc<Mutex<Results>>, targets: Vec<String>, ports: String, time: Arc<Mutex<Duration>>){
+impl Target {
+    pub fn socket_addr(&self, port: u16) -> SocketAddr {
+        match self.ip {
+            IpAddr::V4(_) => SocketAddr::new(self.ip, port),
+            IpAddr::V6(ip) => SocketAddr::V6(SocketAddrV6::new(ip, port, 0, self.scope_id)),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.scope_id, interface_name(self.scope_id)) {
+            (0, _) => write!(f, "{}", self.ip),
+            (_, Some(name)) => write!(f, "{}%{}", self.ip, name),
+            (scope_id, None) => write!(f, "{}%{}", self.ip, scope_id),
+        }
+    }
+}
+
+/// Scan every target, returning the time the scan took. Targets that cannot
+/// be parsed or read end the scan before it starts.
+pub async fn execute_scan(report: Report, targets: Vec<String>, ports: PortList, options: ScanOptions, probes: Arc<AtomicU64>, paused: watch::Receiver<bool>) -> Result<Duration, ScanError> {
     let start_time = Instant::now();
+    if let Some(port) = ports.udp.first() {
+        return Err(PortSpecError::UnsupportedProtocol(port.to_string()).into());
+    }
+
+    // what the scan covers, as recorded in its checkpoint
+    let mut spec = ScanSpec { targets, exclude: Vec::new(), ports: ports.tcp.clone() };
+    spec.ports.sort_unstable();
+    if let Some(path) = &options.input_file {
+        spec.targets.extend(read_target_file(path)?);
+    }
+    for exclude in &options.exclude {
+        spec.exclude.extend(exclude.split(',').map(|exclude| exclude.trim().to_string()));
+    }
+    if let Some(path) = &options.exclude_file {
+        spec.exclude.extend(read_target_file(path)?);
+    }
+    let mut list = TargetList::default();
+    for target in &spec.targets {
+        list.specs.push(TargetSpec::parse(target)?);
+    }
+    for exclude in &spec.exclude {
+        list.exclude.push(TargetSpec::parse(exclude)?);
+    }
+    // names are scanned and excluded by address
+    let mut hostnames = HashMap::new();
+    list.specs = resolve_specs(list.specs, &mut hostnames, &report).await;
+    list.exclude = resolve_specs(list.exclude, &mut HashMap::new(), &report).await;
+    let mut ports = ports.tcp;
+    let mut rng = StdRng::seed_from_u64(options.seed.unwrap_or_else(rand::random));
+    if options.randomize {
+        ports.shuffle(&mut rng);
+    }
+    let ports: Arc<[u16]> = ports.into();
+    report(ScanEvent::Planned { hosts: list.host_count() });
+    let addresses: Box<dyn Iterator<Item = Target> + Send> = match options.randomize {
+        true => Box::new(shuffle_blocks(list.addresses(), rng)),
+        false => Box::new(list.addresses()),
+    };
 
-    let mut ips = Vec::new();
-    for target in targets {
-        let ip = match target.parse::<std::net::IpAddr>() {
-            Ok(ip) => vec![ip],
-            Err(_) => {
-                match parse_cidr(&target) {
-                    Ok(ips) => ips,
-                    Err(e) => {
-                        println!("failed to parse cidr: {}", e);
-                        continue;
+    let checkpoint = match &options.resume {
+        Some(path) => {
+            let checkpoint = Checkpoint::load(path)?;
+            checkpoint.check_spec(&spec)?;
+            Some(checkpoint)
+        }
+        None => None,
+    };
+    let log = match options.checkpoint.clone().or(options.resume.clone()) {
+        Some(path) => {
+            let checkpoint = checkpoint.unwrap_or_else(|| Checkpoint::new(spec));
+            // what is resumed goes into the file first, the scan appends to it
+            write_checkpoint(&checkpoint.to_string(), &path).await.map_err(CheckpointError::Io)?;
+            Some(CheckpointLog { checkpoint: Arc::new(Mutex::new(checkpoint)), path })
+        }
+        None => None,
+    };
+
+    let context = ScanContext {
+        report,
+        ports,
+        reverse_dns: options.reverse_dns,
+        discovery: options.discovery,
+        technique: options.technique,
+        limiter: Limiter::new(&options.timing, probes, paused.clone()),
+        timing: Arc::new(options.timing.clone()),
+        checkpoint: log.as_ref().map(|log| log.checkpoint.clone()),
+    };
+    let timing = context.timing.clone();
+
+    // addresses are expanded lazily and scanned in host groups, so even a /8 only
+    // ever holds one group of hosts in memory. Groups start small for quick first
+    // results and grow up to the maximum group size.
+    let hosts = async {
+        let mut addresses = addresses.peekable();
+        let mut group_size = timing.min_hostgroup.min(timing.max_hostgroup).max(1);
+        while addresses.peek().is_some() {
+            let mut tasks = JoinSet::new();
+            for ip in addresses.by_ref().take(group_size) {
+                if context.resume_host(ip) {
+                    continue;
+                }
+                let host = Arc::new(Mutex::new(HostResult::new(ip, hostnames.get(&ip).cloned())));
+                let scan = scan_host(context.clone(), host.clone());
+                let (context, host_timeout, paused) = (context.clone(), timing.host_timeout, paused.clone());
+                tasks.spawn(async move {
+                    let Some(host_timeout) = host_timeout else {
+                        return scan.await;
+                    };
+                    // a tarpit host must not stall its whole group, what was found
+                    // about it so far is kept
+                    if !within_host_timeout(scan, host_timeout, paused).await {
+                        let mut host = host.lock().unwrap().clone();
+                        host.set_status(HostStatus::Unknown, HostReason::HostTimeout);
+                        context.finish_host(host);
                     }
+                });
+            }
+            while tasks.join_next().await.is_some() {}
+            group_size = (group_size * 2).min(timing.max_hostgroup.max(1));
+        }
+    };
+
+    match &log {
+        Some(log) => {
+            // append every now and then while scanning, and once more at the end
+            let save = async {
+                let mut interval = tokio::time::interval(CHECKPOINT_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    log.append(&context.report).await;
                 }
+            };
+            tokio::select! {
+                _ = hosts => {}
+                _ = save => {}
+            }
+            log.append(&context.report).await;
+        }
+        None => hosts.await,
+    }
+    if let Some(min_rate) = timing.min_rate
+        && context.limiter.below_min_rate() {
+        let rate = context.limiter.rate();
+        (context.report)(ScanEvent::Warning(ScanError::MinRateMissed { min_rate, rate }));
+    }
+
+    Ok(start_time.elapsed())
+}
+
+fn read_target_file(path: &Path) -> Result<Vec<String>, TargetError> {
+    target::read_target_file(path).map_err(|error| TargetError::File { path: path.to_path_buf(), error })
+}
+
+/// Everything the scan of a single host shares with the rest of the scan
+#[derive(Clone)]
+struct ScanContext {
+    report: Report,
+    ports: Arc<[u16]>,
+    reverse_dns: bool,
+    discovery: Discovery,
+    technique: Technique,
+    limiter: Limiter,
+    timing: Arc<Timing>,
+    checkpoint: Option<Arc<Mutex<Checkpoint>>>,
+}
+
+impl ScanContext {
+    /// Report the results of a host that is done with
+    fn finish_host(&self, mut host: HostResult) {
+        host.finish();
+        if let Some(checkpoint) = &self.checkpoint {
+            checkpoint.lock().unwrap().host_done(&host);
+        }
+        (self.report)(ScanEvent::HostDone(host));
+    }
+
+    /// Take over the results of a host the resumed scan already finished,
+    /// returning whether there is nothing left to do for it
+    fn resume_host(&self, ip: Target) -> bool {
+        let Some(checkpoint) = &self.checkpoint else {
+            return false;
+        };
+        let finished = checkpoint.lock().unwrap().finished.remove(&ip.ip);
+        match finished {
+            Some(mut host) => {
+                // the checkpoint only has the address, not the scope it was found through
+                host.target = ip;
+                (self.report)(ScanEvent::HostDone(host));
+                true
             }
+            None => false,
+        }
+    }
+
+    /// Ports of a host the resumed scan already probed
+    fn resumed_ports(&self, ip: Target) -> Vec<ScanResult> {
+        match &self.checkpoint {
+            Some(checkpoint) => checkpoint.lock().unwrap().partial.remove(&ip.ip).unwrap_or_default(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Appends the records of a scan to its checkpoint file. Records still pending
+/// are written when the scan is dropped, so cancelling it loses no progress.
+struct CheckpointLog {
+    checkpoint: Arc<Mutex<Checkpoint>>,
+    path: PathBuf,
+}
+
+impl CheckpointLog {
+    /// Write the records of the hosts and ports completed since the last append
+    async fn append(&self, report: &Report) {
+        let records = self.checkpoint.lock().unwrap().take_pending();
+        if records.is_empty() {
+            return;
+        }
+        let appended = async {
+            let mut file = tokio::fs::OpenOptions::new().append(true).open(&self.path).await?;
+            file.write_all(records.as_bytes()).await?;
+            file.flush().await
         };
-        ips.extend(ip);
+        if let Err(e) = appended.await {
+            self.checkpoint.lock().unwrap().restore_pending(&records);
+            report(ScanEvent::Warning(ScanError::Checkpoint(CheckpointError::Io(e))));
+        }
     }
+}
 
-    let mut ping_handles = Vec::new();
-    for ip in ips {
-        let ping_future = tokio::spawn(async move {
-            let is_alive = ping(ip).await;
-            (ip, is_alive)
-        });
-        ping_handles.push(ping_future);
+impl Drop for CheckpointLog {
+    fn drop(&mut self) {
+        let Ok(mut checkpoint) = self.checkpoint.lock() else {
+            return;
+        };
+        let records = checkpoint.take_pending();
+        if !records.is_empty() {
+            // nobody is left to report a failure to
+            let _ = fs::OpenOptions::new().append(true).open(&self.path)
+                .and_then(|mut file| file.write_all(records.as_bytes()));
+        }
     }
+}
 
-    let mut ping_results = Vec::new();
-    for handle in ping_handles {
-        if let Ok(result) = handle.await {
-            ping_results.push(result);
+/// Write a whole checkpoint next to its file first, so a crash while writing
+/// never leaves a truncated file behind
+async fn write_checkpoint(contents: &str, path: &Path) -> io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    tokio::fs::write(&temp, contents).await?;
+    tokio::fs::rename(&temp, path).await
+}
+
+/// Ping a host and scan its ports if it is up. What is found is kept in `host`
+/// as it comes in, so a host given up on keeps it.
+async fn scan_host(context: ScanContext, host: Arc<Mutex<HostResult>>) {
+    let ScanContext { limiter, timing, .. } = &context;
+    let ip = host.lock().unwrap().target;
+    match context.discovery {
+        Discovery::Ping => {
+            let permit = limiter.acquire().await;
+            limiter.pace().await;
+            let ping_rtt = ping(ip, timing.ping_timeout).await;
+            drop(permit);
+            let mut found = host.lock().unwrap();
+            match ping_rtt {
+                Ok(Some(ping_rtt)) => {
+                    found.set_status(HostStatus::Up, HostReason::EchoReply);
+                    found.latency = Some(ping_rtt);
+                }
+                Ok(None) => {
+                    found.set_status(HostStatus::Down, HostReason::NoResponse);
+                    context.finish_host(found.clone());
+                    return;
+                }
+                Err(e) => {
+                    (context.report)(ScanEvent::Warning(e));
+                    found.set_status(HostStatus::Unknown, HostReason::LocalError);
+                    context.finish_host(found.clone());
+                    return;
+                }
+            }
         }
+        Discovery::Skip => host.lock().unwrap().set_status(HostStatus::Up, HostReason::UserSet),
+    }
+    let named = !host.lock().unwrap().hostnames.is_empty();
+    if !named
+        && context.reverse_dns
+        && let Some(hostname) = reverse_lookup(ip).await {
+        host.lock().unwrap().hostnames.push(hostname);
     }
-    
-    let mut scan_handles = Vec::new();
-    for (ip, is_alive) in ping_results {
-        let ports_clone = ports.clone();
-        let state_clone = state.clone();
-        
-        if is_alive {
-            let handle = tokio::spawn(async move {
-                let results = scan::scan_ports(ip, ports_clone.as_str()).await;
-                // println!("insert ip addr: {}", ip);
-                state_clone.lock().unwrap().insert(ip.to_string(), results);
-            });
-            scan_handles.push(handle);
-        } else {
-            state.lock().unwrap().insert(format!("{} is not reachable", ip), Vec::new());
+    let up = host.lock().unwrap().clone();
+    // the echo reply gives the first round trip time sample of the host
+    let mut rtt = RttEstimator::new(timing);
+    if let Some(ping_rtt) = up.latency {
+        rtt.update(ping_rtt);
+    }
+    let rtt = Arc::new(Mutex::new(rtt));
+    (context.report)(ScanEvent::HostUp(up));
+    // ports probed before the scan was interrupted are not probed again
+    let mut results = context.resumed_ports(ip);
+    for result in &results {
+        (context.report)(ScanEvent::Port { target: ip, result: result.clone() });
+    }
+    host.lock().unwrap().ports = results.clone();
+    let resumed: HashSet<u16> = results.iter().map(|result| result.port).collect();
+    let ports: Vec<u16> = context.ports.iter()
+        .filter(|port| !resumed.contains(port))
+        .copied()
+        .collect();
+    let on_result = |result: &ScanResult| {
+        if let Some(checkpoint) = &context.checkpoint {
+            checkpoint.lock().unwrap().port_done(ip.ip, result);
+        }
+        let mut found = host.lock().unwrap();
+        found.ports.push(result.clone());
+        found.latency = rtt.lock().unwrap().srtt().or(found.latency);
+        drop(found);
+        (context.report)(ScanEvent::Port { target: ip, result: result.clone() });
+    };
+    let on_error = |error| (context.report)(ScanEvent::Warning(error));
+    let scanned = match context.technique {
+        Technique::Connect => scan::scan_ports(ip, &ports, limiter, timing, rtt.clone(), on_result, on_error).await,
+    };
+    results.extend(scanned);
+    // the probes put a host on the local network into the ARP cache
+    let mac = match ip.ip {
+        IpAddr::V4(address) => arp_lookup(address).await,
+        IpAddr::V6(_) => None,
+    };
+    let mut found = host.lock().unwrap();
+    // in the order they were probed, with the ports that could not be probed
+    found.ports = results;
+    found.latency = rtt.lock().unwrap().srtt().or(found.latency);
+    found.mac = mac;
+    context.finish_host(found.clone());
+}
+
+/// Run the scan of a host for at most `host_timeout`, not counting the time the
+/// scan is paused. Returns whether the scan finished in time.
+async fn within_host_timeout(scan: impl Future<Output = ()>, host_timeout: Duration, mut paused: watch::Receiver<bool>) -> bool {
+    let mut scan = pin!(scan);
+    let mut remaining = host_timeout;
+    loop {
+        let start = Instant::now();
+        tokio::select! {
+            _ = &mut scan => return true,
+            _ = tokio::time::sleep(remaining) => return false,
+            Ok(()) = async { paused.wait_for(|paused| *paused).await.map(drop) } => {
+                remaining = remaining.saturating_sub(start.elapsed());
+                let _ = paused.wait_for(|paused| !paused).await;
+            }
         }
     }
+}
+
+/// Shuffle a (possibly huge) stream of hosts in blocks of HOST_SHUFFLE_BLOCK,
+/// so the order is random without collecting the whole stream
+fn shuffle_blocks(mut hosts: impl Iterator<Item = Target>, mut rng: StdRng) -> impl Iterator<Item = Target> {
+    let mut block = Vec::new();
+    std::iter::from_fn(move || {
+        if block.is_empty() {
+            block.extend(hosts.by_ref().take(HOST_SHUFFLE_BLOCK));
+            block.shuffle(&mut rng);
+        }
+        block.pop()
+    })
+}
 
-    for handle in scan_handles {
-        let _ = handle.await;
+/// Replace hostname specifications by the addresses they resolve to,
+/// remembering the name each address was found under
+async fn resolve_specs(specs: Vec<TargetSpec>, hostnames: &mut HashMap<Target, String>, report: &Report) -> Vec<TargetSpec> {
+    let mut resolved = Vec::new();
+    for spec in specs {
+        match spec {
+            TargetSpec::Hostname(name) => match resolve(&name).await {
+                Ok(targets) => for target in targets {
+                    hostnames.entry(target).or_insert_with(|| name.clone());
+                    resolved.push(TargetSpec::Address(target));
+                },
+                // like nmap, a name that does not resolve is skipped
+                Err(error) => report(ScanEvent::Warning(TargetError::Resolve { name, error }.into())),
+            },
+            spec => resolved.push(spec),
+        }
     }
-    
-    let end_time = Instant::now();
-    *time.lock().unwrap() = end_time.duration_since(start_time);
-} 
+    resolved
+}
 
+/// Hardware address of a host on a local network, from the ARP cache of the
+/// kernel. Only Linux exposes it, as `/proc/net/arp`.
+async fn arp_lookup(ip: Ipv4Addr) -> Option<MacAddr> {
+    let table = tokio::fs::read_to_string("/proc/net/arp").await.ok()?;
+    // IP address, HW type, Flags, HW address, Mask, Device; flags 0x0 is an
+    // entry still waiting for an answer
+    table.lines().skip(1).find_map(|line| {
+        match line.split_whitespace().collect::<Vec<_>>()[..] {
+            [address, _, flags, mac, ..] if address.parse() == Ok(ip) && flags != "0x0" => MacAddr::parse(mac),
+            _ => None,
+        }
+    })
+}
+
+
+/// Send an ICMP (v4) or ICMPv6 echo request to the target, returning the round
+/// trip time if it answered
+async fn ping(target: Target, timeout: Duration) -> Result<Option<Duration>, ScanError> {
+    let config = match target.ip {
+        IpAddr::V4(_) => Config::default(),
+        IpAddr::V6(_) => {
+            let mut builder = Config::builder().kind(ICMP::V6);
+            // link-local addresses are only reachable through their own interface
+            if let Some(name) = interface_name(target.scope_id) {
+                builder = builder.interface(&name);
+            }
+            builder.build()
+        }
+    };
+    let client = Client::new(&config)?;
+    let mut pinger = client.pinger(target.ip, PingIdentifier(rand::random())).await;
+    pinger.timeout(timeout);
+    match pinger.ping(PingSequence(0), &[1, 2, 3, 4]).await {
+        Ok((_, rtt)) => Ok(Some(rtt)),
+        Err(SurgeError::IOError(e)) => Err(e.into()),
+        // timeouts and unexpected replies
+        Err(_) => Ok(None),
+    }
+}
 
+/// Resolve every A and AAAA record of a hostname. Uses the system resolver,
+/// so /etc/hosts is honoured.
+pub async fn resolve(hostname: &str) -> std::io::Result<Vec<Target>> {
+    let mut targets = Vec::new();
+    for addr in tokio::net::lookup_host((hostname, 0)).await? {
+        let target = Target {
+            ip: addr.ip(),
+            scope_id: match addr {
+                SocketAddr::V6(addr) => addr.scope_id(),
+                SocketAddr::V4(_) => 0,
+            },
+        };
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+    }
+    Ok(targets)
+}
 
+/// Look up the PTR name of an address with the system resolver
+pub async fn reverse_lookup(target: Target) -> Option<String> {
+    tokio::task::spawn_blocking(move || getnameinfo(target)).await.ok().flatten()
+}
 
-async fn ping(ip: std::net::IpAddr) -> bool {
-    use surge_ping::ping;
-    match ping(ip, &[1, 2, 3, 4]).await {
-        Ok(_) => {
-            true
+fn getnameinfo(target: Target) -> Option<String> {
+    // NI_MAXHOST
+    let mut host = [0; 1025];
+    let ret = match target.ip {
+        IpAddr::V4(ip) => {
+            // SAFETY: sockaddr_in is plain old data, all zeroes is a valid value
+            let mut addr: libc::sockaddr_in = unsafe { mem::zeroed() };
+            addr.sin_family = libc::AF_INET as libc::sa_family_t;
+            addr.sin_addr.s_addr = u32::from_ne_bytes(ip.octets());
+            // SAFETY: `addr` and `host` outlive the call and their sizes are passed along
+            unsafe {
+                libc::getnameinfo(
+                    &addr as *const libc::sockaddr_in as *const libc::sockaddr,
+                    mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+                    host.as_mut_ptr(),
+                    host.len() as libc::socklen_t,
+                    ptr::null_mut(),
+                    0,
+                    libc::NI_NAMEREQD,
+                )
+            }
         }
-        Err(_) => {
-            false
+        IpAddr::V6(ip) => {
+            // SAFETY: sockaddr_in6 is plain old data, all zeroes is a valid value
+            let mut addr: libc::sockaddr_in6 = unsafe { mem::zeroed() };
+            addr.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            addr.sin6_addr.s6_addr = ip.octets();
+            addr.sin6_scope_id = target.scope_id;
+            // SAFETY: `addr` and `host` outlive the call and their sizes are passed along
+            unsafe {
+                libc::getnameinfo(
+                    &addr as *const libc::sockaddr_in6 as *const libc::sockaddr,
+                    mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t,
+                    host.as_mut_ptr(),
+                    host.len() as libc::socklen_t,
+                    ptr::null_mut(),
+                    0,
+                    libc::NI_NAMEREQD,
+                )
+            }
         }
+    };
+    if ret != 0 {
+        return None;
     }
+    // SAFETY: on success `host` holds a NUL terminated name
+    let name = unsafe { CStr::from_ptr(host.as_ptr()) };
+    Some(name.to_string_lossy().into_owned())
 }
 
-pub fn parse_cidr(cidr: &str) -> Result<Vec<std::net::IpAddr>, Box<dyn std::error::Error>> {
-    let network = cidr.parse::<ipnetwork::IpNetwork>()?;
-    Ok(network.iter().collect())
-}
\ No newline at end of file
+fn interface_name(index: u32) -> Option<String> {
+    if index == 0 {
+        return None;
+    }
+    let mut buf = [0; libc::IF_NAMESIZE];
+    // SAFETY: `buf` has room for IF_NAMESIZE bytes as required by if_indextoname
+    let name = unsafe { libc::if_indextoname(index, buf.as_mut_ptr()) };
+    if name.is_null() {
+        return None;
+    }
+    // SAFETY: on success `buf` holds a NUL terminated interface name
+    let name = unsafe { CStr::from_ptr(buf.as_ptr()) };
+    Some(name.to_string_lossy().into_owned())
+}
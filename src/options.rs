@@ -0,0 +1,210 @@
+use std::{fmt, path::{Path, PathBuf}, time::Duration};
+
+use crate::{net::Discovery, scan::Technique, target::TargetSpec, timing::{self, Timing, TimingTemplate}};
+
+/// Scan settings that are not part of the target or port specification
+#[derive(Clone, Debug)]
+pub struct ScanOptions {
+    /// How hosts are found to be up
+    pub discovery: Discovery,
+    /// How ports are probed
+    pub technique: Technique,
+    /// Look up the PTR name of every live host
+    pub reverse_dns: bool,
+    /// File of additional target specifications, `-` for standard input
+    pub input_file: Option<PathBuf>,
+    /// Comma separated target specifications never to scan
+    pub exclude: Vec<String>,
+    /// File of target specifications never to scan
+    pub exclude_file: Option<PathBuf>,
+    /// Probe hosts and ports in random order instead of sequentially
+    pub randomize: bool,
+    /// Seed of the random order, a scan with the same seed probes in the same order
+    pub seed: Option<u64>,
+    /// Timeouts, parallelism, retries and delays, from a template plus overrides
+    pub timing: Timing,
+    /// File the progress of the scan is saved to every now and then
+    pub checkpoint: Option<PathBuf>,
+    /// Checkpoint of an interrupted run of the same scan; the hosts and ports in it
+    /// are not probed again, and it is kept up to date unless --checkpoint is given
+    pub resume: Option<PathBuf>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            discovery: Discovery::Ping,
+            technique: Technique::Connect,
+            reverse_dns: false,
+            input_file: None,
+            exclude: Vec::new(),
+            exclude_file: None,
+            randomize: true,
+            seed: None,
+            timing: Timing::default(),
+            checkpoint: None,
+            resume: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OptionsError {
+    UnknownOption(String),
+    MissingValue(String),
+    InvalidValue { option: String, value: String },
+}
+
+impl fmt::Display for OptionsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptionsError::UnknownOption(option) => write!(f, "unknown option {:?}", option),
+            OptionsError::MissingValue(option) => write!(f, "{} needs a value", option),
+            OptionsError::InvalidValue { option, value } => {
+                write!(f, "invalid value {:?} for {}", value, option)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OptionsError {}
+
+impl ScanOptions {
+    /// Whether a target or exclusion list is read from standard input (`-`),
+    /// which the interface cannot offer as it reads keys from the terminal
+    pub fn reads_stdin(&self) -> bool {
+        [&self.input_file, &self.exclude_file].iter().any(|path| path.as_deref() == Some(Path::new("-")))
+    }
+
+    /// Parse a whitespace separated list of nmap style flags, e.g. `-R --exclude 10.0.0.1`
+    pub fn parse(input: &str) -> Result<Self, OptionsError> {
+        let mut options = Self::default();
+        options.apply(input.split_whitespace())?;
+        Ok(options)
+    }
+
+    /// Apply nmap style flags on top of the current options. A timing template
+    /// (`-T4`) is applied first, so individual timing options always override it.
+    pub fn apply<'a>(&mut self, args: impl IntoIterator<Item = &'a str>) -> Result<(), OptionsError> {
+        let args: Vec<&str> = args.into_iter().collect();
+        match self.apply_args(args.iter().copied())?.first() {
+            Some(&operand) => Err(OptionsError::UnknownOption(args[operand].to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Like [`ScanOptions::apply`], but arguments that are neither flags nor their
+    /// values are left alone instead of rejected, e.g. the targets of a command
+    /// line. Returns their positions among `args`.
+    pub fn apply_args<'a>(&mut self, args: impl IntoIterator<Item = &'a str>) -> Result<Vec<usize>, OptionsError> {
+        let args: Vec<&str> = args.into_iter().collect();
+        let mut operands = Vec::new();
+        for (index, &arg) in args.iter().enumerate() {
+            let template = match arg {
+                "-T" => value(arg, args.get(index + 1).copied())?,
+                _ => match arg.strip_prefix("-T") {
+                    Some(template) => template,
+                    None => continue,
+                },
+            };
+            let template = TimingTemplate::parse(template).ok_or_else(|| invalid("-T", template))?;
+            self.timing = template.timing();
+        }
+
+        let total = args.len();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // the values of earlier flags are taken from `args` as well
+            let index = total - args.len() - 1;
+            match arg {
+                // timing templates were applied above
+                "-T" => {
+                    args.next();
+                }
+                _ if arg.starts_with("-T") => {}
+                "-sT" => self.technique = Technique::Connect,
+                // skip host discovery, scan every host
+                "-Pn" => self.discovery = Discovery::Skip,
+                // never do reverse DNS resolution
+                "-n" => self.reverse_dns = false,
+                // always resolve the names of live hosts
+                "-R" => self.reverse_dns = true,
+                "-iL" => self.input_file = Some(PathBuf::from(value(arg, args.next())?)),
+                "--exclude" => {
+                    let value = value(arg, args.next())?;
+                    if let Some(Err(_)) = value.split(',').map(TargetSpec::parse).find(Result::is_err) {
+                        return Err(invalid(arg, value));
+                    }
+                    self.exclude.push(value.to_string());
+                }
+                "--excludefile" => self.exclude_file = Some(PathBuf::from(value(arg, args.next())?)),
+                "--checkpoint" => self.checkpoint = Some(PathBuf::from(value(arg, args.next())?)),
+                "--resume" => self.resume = Some(PathBuf::from(value(arg, args.next())?)),
+                // keep hosts and ports in the order they were specified
+                "-r" | "--sequential" => self.randomize = false,
+                "--seed" => {
+                    let value = value(arg, args.next())?;
+                    self.seed = Some(value.parse().map_err(|_| invalid(arg, value))?);
+                }
+                "--max-parallelism" => self.timing.max_parallelism = count(arg, args.next())?,
+                "--max-host-parallelism" => self.timing.max_host_parallelism = count(arg, args.next())?,
+                "--max-retries" => {
+                    let value = value(arg, args.next())?;
+                    self.timing.max_retries = value.parse().map_err(|_| invalid(arg, value))?;
+                }
+                "--initial-rtt-timeout" => self.timing.initial_rtt_timeout = duration(arg, args.next())?,
+                "--min-rtt-timeout" => self.timing.min_rtt_timeout = duration(arg, args.next())?,
+                "--max-rtt-timeout" => self.timing.max_rtt_timeout = duration(arg, args.next())?,
+                "--ping-timeout" => self.timing.ping_timeout = duration(arg, args.next())?,
+                "--scan-delay" => self.timing.scan_delay = duration(arg, args.next())?,
+                "--max-scan-delay" => self.timing.max_scan_delay = duration(arg, args.next())?,
+                "--max-rate" => self.timing.max_rate = Some(rate(arg, args.next())?),
+                "--min-rate" => self.timing.min_rate = Some(rate(arg, args.next())?),
+                "--min-hostgroup" => self.timing.min_hostgroup = count(arg, args.next())?,
+                "--max-hostgroup" => self.timing.max_hostgroup = count(arg, args.next())?,
+                "--host-timeout" => self.timing.host_timeout = Some(duration(arg, args.next())?),
+                _ if !arg.starts_with('-') => operands.push(index),
+                _ => return Err(OptionsError::UnknownOption(arg.to_string())),
+            }
+        }
+        if let (Some(min_rate), Some(max_rate)) = (self.timing.min_rate, self.timing.max_rate)
+            && min_rate > max_rate {
+            return Err(invalid("--min-rate", &min_rate.to_string()));
+        }
+        Ok(operands)
+    }
+}
+
+fn value<'a>(option: &str, value: Option<&'a str>) -> Result<&'a str, OptionsError> {
+    value.ok_or_else(|| OptionsError::MissingValue(option.to_string()))
+}
+
+/// A value that has to be a positive number
+fn count(option: &str, value: Option<&str>) -> Result<usize, OptionsError> {
+    let value = self::value(option, value)?;
+    match value.parse() {
+        Ok(0) | Err(_) => Err(invalid(option, value)),
+        Ok(count) => Ok(count),
+    }
+}
+
+/// Probes per second, a positive number
+fn rate(option: &str, value: Option<&str>) -> Result<f64, OptionsError> {
+    let value = self::value(option, value)?;
+    match value.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err(invalid(option, value)),
+    }
+}
+
+fn duration(option: &str, value: Option<&str>) -> Result<Duration, OptionsError> {
+    let value = self::value(option, value)?;
+    timing::parse_duration(value).ok_or_else(|| invalid(option, value))
+}
+
+fn invalid(option: &str, value: &str) -> OptionsError {
+    OptionsError::InvalidValue {
+        option: option.to_string(),
+        value: value.to_string(),
+    }
+}
@@ -1,98 +1,700 @@
-use std::{net::{IpAddr, SocketAddr}, time::Duration};
+use std::{collections::HashSet, fmt, io, sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex}, time::Duration};
 
-use ratatui::text::Text;
-use tokio::net::TcpSocket;
-#[derive(Clone)]
+use tokio::{net::{TcpSocket, TcpStream}, sync::{watch, OwnedSemaphorePermit, Semaphore}, task::{JoinError, JoinSet}, time::Instant};
+
+use crate::{error::ScanError, net::Target, services, timing::{Backoff, RttEstimator, Timing}};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ScanResult {
     pub port: u16,
-    pub is_open: bool,
+    pub protocol: Protocol,
+    pub state: PortState,
+    pub reason: Reason,
+    /// Times the probe was resent because it went unanswered
+    pub retries: u32,
+}
+
+/// How ports are probed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Technique {
+    /// Full TCP handshake through the operating system (`-sT`), needs no privileges
+    #[default]
+    Connect,
 }
 
-impl<'a> Into<Text<'a>> for ScanResult {
-    fn into(self) -> Text<'a> {
-        Text::from(format!("{} {}", self.port, if self.is_open { "Open" } else { "Closed" }))
+/// State of a probed port, following nmap's terminology
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PortState {
+    /// Something accepted the connection
+    Open,
+    /// The host answered but nothing listens on the port
+    Closed,
+    /// No answer, or an ICMP error, usually because of a firewall
+    Filtered,
+    /// The probe could not be sent, e.g. the local socket could not be created
+    Error,
+}
+
+impl fmt::Display for PortState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortState::Open => write!(f, "open"),
+            PortState::Closed => write!(f, "closed"),
+            PortState::Filtered => write!(f, "filtered"),
+            PortState::Error => write!(f, "error"),
+        }
     }
 }
 
-/// Scan a single port using TCP
-pub async fn scan_port(ip: IpAddr, port: u16) -> ScanResult {
-    let socket_addr = SocketAddr::new(ip, port);
-    let socket = TcpSocket::new_v4().unwrap();
-    match socket.connect(socket_addr).await {
-        Ok(_) => {
-            // println!("{}:{} is open", ip, port);
-            ScanResult {
-                port,
-                is_open: true,
+/// Why a port was given its state
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reason {
+    SynAck,
+    ConnRefused,
+    NoResponse,
+    HostUnreach,
+    NetUnreach,
+    LocalError,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::SynAck => write!(f, "syn-ack"),
+            Reason::ConnRefused => write!(f, "conn-refused"),
+            Reason::NoResponse => write!(f, "no-response"),
+            Reason::HostUnreach => write!(f, "host-unreach"),
+            Reason::NetUnreach => write!(f, "net-unreach"),
+            Reason::LocalError => write!(f, "error"),
+        }
+    }
+}
+
+/// Map the outcome of a connect attempt to a port state. Errors that say
+/// nothing about the port are passed on.
+fn classify(connect: io::Result<TcpStream>) -> Result<(PortState, Reason), ScanError> {
+    match connect {
+        Ok(_) => Ok((PortState::Open, Reason::SynAck)),
+        Err(e) => match e.kind() {
+            io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset => {
+                Ok((PortState::Closed, Reason::ConnRefused))
             }
+            io::ErrorKind::TimedOut => Ok((PortState::Filtered, Reason::NoResponse)),
+            io::ErrorKind::HostUnreachable => Ok((PortState::Filtered, Reason::HostUnreach)),
+            io::ErrorKind::NetworkUnreachable => Ok((PortState::Filtered, Reason::NetUnreach)),
+            _ => Err(e.into()),
         },
-        Err(_) => {
-            // println!("{}:{} is closed", ip, port);
-            ScanResult {
-                port,
-                is_open: false,
+    }
+}
+
+/// Transport protocol a port in a port specification applies to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Protocol::Tcp => write!(f, "tcp"),
+            Protocol::Udp => write!(f, "udp"),
+        }
+    }
+}
+
+/// Ports selected by a port specification, split by protocol and de-duplicated,
+/// in the order they should be probed
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PortList {
+    pub tcp: Vec<u16>,
+    /// Parsed, but not probed yet: a scan with UDP ports fails
+    pub udp: Vec<u16>,
+}
+
+/// Largest `n` accepted by [`top_ports`]
+pub const MAX_TOP_PORTS: usize = 1000;
+
+/// The 100 TCP ports most often found open, most frequent first (nmap-services frequencies)
+const TOP_TCP_PORTS_RANKED: [u16; 100] = [
+    80, 23, 443, 21, 22, 25, 3389, 110, 445, 139, 143, 53,
+    135, 3306, 8080, 1723, 111, 995, 993, 5900, 1025, 587, 8888, 199,
+    1720, 465, 548, 113, 81, 6001, 10000, 514, 5060, 179, 1026, 2000,
+    8443, 8000, 32768, 554, 26, 1433, 49152, 2001, 515, 8008, 49154, 1027,
+    5666, 646, 5000, 5631, 631, 49153, 8081, 2049, 88, 79, 5800, 106,
+    2121, 1110, 49155, 6000, 513, 990, 5357, 427, 49156, 543, 544, 5101,
+    144, 7, 389, 8009, 3128, 444, 9999, 5009, 7070, 5190, 3000, 5432,
+    1900, 3986, 13, 1029, 9, 5051, 6646, 49157, 1028, 873, 1755, 2717,
+    4899, 9100, 119, 37,
+];
+
+/// Every port of the top 1000 TCP ports. Ports not in [`TOP_TCP_PORTS_RANKED`] share
+/// roughly the same frequency and are ranked in port order.
+const TOP_1000_TCP_PORTS: &str = concat!(
+    "1,3-4,6-7,9,13,17,19-26,30,32-33,37,42-43,49,53,70,79-85,88-90,99-100,106,109-111,113,",
+    "119,125,135,139,143-144,146,161,163,179,199,211-212,222,254-256,259,264,280,301,306,311,",
+    "340,366,389,406-407,416-417,425,427,443-445,458,464-465,481,497,500,512-515,524,541,",
+    "543-545,548,554-555,563,587,593,616-617,625,631,636,646,648,666-668,683,687,691,700,705,",
+    "711,714,720,722,726,749,765,777,783,787,800-801,808,843,873,880,888,898,900-903,911-912,",
+    "981,987,990,992-993,995,999-1002,1007,1009-1011,1021-1100,1102,1104-1108,1110-1114,1117,",
+    "1119,1121-1124,1126,1130-1132,1137-1138,1141,1145,1147-1149,1151-1152,1154,1163-1166,",
+    "1169,1174-1175,1183,1185-1187,1192,1198-1199,1201,1213,1216-1218,1233-1234,1236,1244,",
+    "1247-1248,1259,1271-1272,1277,1287,1296,1300-1301,1309-1311,1322,1328,1334,1352,1417,",
+    "1433-1434,1443,1455,1461,1494,1500-1501,1503,1521,1524,1533,1556,1580,1583,1594,1600,",
+    "1641,1658,1666,1687-1688,1700,1717-1721,1723,1755,1761,1782-1783,1801,1805,1812,",
+    "1839-1840,1862-1864,1875,1900,1914,1935,1947,1971-1972,1974,1984,1998-2010,2013,",
+    "2020-2022,2030,2033-2035,2038,2040-2043,2045-2049,2065,2068,2099-2100,2103,2105-2107,",
+    "2111,2119,2121,2126,2135,2144,2160-2161,2170,2179,2190-2191,2196,2200,2222,2251,2260,",
+    "2288,2301,2323,2366,2381-2383,2393-2394,2399,2401,2492,2500,2522,2525,2557,2601-2602,",
+    "2604-2605,2607-2608,2638,2701-2702,2710,2717-2718,2725,2800,2809,2811,2869,2875,",
+    "2909-2910,2920,2967-2968,2998,3000-3001,3003,3005-3007,3011,3013,3017,3030-3031,3052,",
+    "3071,3077,3128,3168,3211,3221,3260-3261,3268-3269,3283,3300-3301,3306,3322-3325,3333,",
+    "3351,3367,3369-3372,3389-3390,3404,3476,3493,3517,3527,3546,3551,3580,3659,3689-3690,",
+    "3703,3737,3766,3784,3800-3801,3809,3814,3826-3828,3851,3869,3871,3878,3880,3889,3905,",
+    "3914,3918,3920,3945,3971,3986,3995,3998,4000-4006,4045,4111,4125-4126,4129,4224,4242,",
+    "4279,4321,4343,4443-4446,4449,4550,4567,4662,4848,4899-4900,4998,5000-5004,5009,5030,",
+    "5033,5050-5051,5054,5060-5061,5080,5087,5100-5102,5120,5190,5200,5214,5221-5222,",
+    "5225-5226,5269,5280,5298,5357,5405,5414,5431-5432,5440,5500,5510,5544,5550,5555,5560,",
+    "5566,5631,5633,5666,5678-5679,5718,5730,5800-5802,5810-5811,5815,5822,5825,5850,5859,",
+    "5862,5877,5900-5904,5906-5907,5910-5911,5915,5922,5925,5950,5952,5959-5963,5987-5989,",
+    "5998-6007,6009,6025,6059,6100-6101,6106,6112,6123,6129,6156,6346,6389,6502,6510,6543,",
+    "6547,6565-6567,6580,6646,6666-6669,6689,6692,6699,6779,6788-6789,6792,6839,6881,6901,",
+    "6969,7000-7002,7004,7007,7019,7025,7070,7100,7103,7106,7200-7201,7402,7435,7443,7496,",
+    "7512,7625,7627,7676,7741,7777-7778,7800,7911,7920-7921,7937-7938,7999-8002,8007-8011,",
+    "8021-8022,8031,8042,8045,8080-8090,8093,8099-8100,8180-8181,8192-8194,8200,8222,8254,",
+    "8290-8292,8300,8333,8383,8400,8402,8443,8500,8600,8649,8651-8652,8654,8701,8800,8873,",
+    "8888,8899,8994,9000-9003,9009-9011,9040,9050,9071,9080-9081,9090-9091,9099-9103,",
+    "9110-9111,9200,9207,9220,9290,9415,9418,9485,9500,9502-9503,9535,9575,9593-9595,9618,",
+    "9666,9876-9878,9898,9900,9917,9929,9943-9944,9968,9998-10004,10009-10010,10012,",
+    "10024-10025,10082,10180,10215,10243,10566,10616-10617,10621,10626,10628-10629,10778,",
+    "11110-11111,11967,12000,12174,12265,12345,13456,13722,13782-13783,14000,14238,",
+    "14441-14442,15000,15002-15004,15660,15742,16000-16001,16012,16016,16018,16080,16113,",
+    "16992-16993,17877,17988,18040,18101,18988,19101,19283,19315,19350,19780,19801,19842,",
+    "20000,20005,20031,20221-20222,20828,21571,22939,23502,24444,24800,25734-25735,26214,",
+    "27000,27352-27353,27355-27356,27715,28201,30000,30718,30951,31038,31337,32768-32785,",
+    "33354,33899,34571-34573,35500,38292,40193,40911,41511,42510,44176,44442-44443,44501,",
+    "45100,48080,49152-49161,49163,49165,49167,49175-49176,49400,49999-50003,50006,50300,",
+    "50389,50500,50636,50800,51103,51493,52673,52822,52848,52869,54045,54328,55055-55056,",
+    "55555,55600,56737-56738,57294,57797,58080,60020,60443,61532,61900,62078,63331,64623,",
+    "64680,65000,65129,65389",
+);
+
+/// The `n` most frequently open TCP ports, most frequent first, like nmap's `--top-ports`
+pub fn top_ports(n: usize) -> Result<Vec<u16>, PortSpecError> {
+    if n == 0 || n > MAX_TOP_PORTS {
+        return Err(PortSpecError::InvalidTopPorts(n.to_string()));
+    }
+    let mut ports = TOP_TCP_PORTS_RANKED.to_vec();
+    if n > ports.len() {
+        let rest = parse_ports_range(TOP_1000_TCP_PORTS)?.tcp;
+        ports.extend(rest.into_iter().filter(|port| !TOP_TCP_PORTS_RANKED.contains(port)));
+    }
+    ports.truncate(n);
+    Ok(ports)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PortSpecError {
+    /// The specification (or one of its comma separated items) is empty
+    Empty,
+    /// A port number that is not in 0-65535
+    InvalidPort(String),
+    /// A range whose start is greater than its end
+    InvalidRange(String),
+    /// A protocol prefix other than `T:` or `U:`
+    UnknownProtocol(String),
+    /// Ports of a protocol that cannot be probed yet, i.e. UDP, as a scan reports them
+    UnsupportedProtocol(String),
+    /// A `top:N` item whose count is not in 1-1000
+    InvalidTopPorts(String),
+    /// A service name that is not in the services table for the protocol
+    UnknownService(String),
+}
+
+impl fmt::Display for PortSpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortSpecError::Empty => write!(f, "empty port specification"),
+            PortSpecError::InvalidPort(port) => write!(f, "invalid port {:?}", port),
+            PortSpecError::InvalidRange(range) => write!(f, "invalid port range {:?}", range),
+            PortSpecError::UnknownProtocol(proto) => write!(f, "unknown protocol prefix {:?}", proto),
+            PortSpecError::UnsupportedProtocol(ports) => write!(f, "UDP ports {:?} cannot be scanned yet", ports),
+            PortSpecError::InvalidTopPorts(count) => {
+                write!(f, "top ports count {:?} is not in 1-{}", count, MAX_TOP_PORTS)
             }
+            PortSpecError::UnknownService(name) => write!(f, "unknown service {:?}", name),
         }
     }
 }
 
-pub async fn scan_ports(ip: IpAddr, ports: &str) -> Vec<ScanResult> {
-    let ports_vec = parse_ports_range(ports);
-    
-    // create multiple concurrent tasks
-    let mut handles = Vec::new();
-    for port in ports_vec {
-        let handle = tokio::spawn(async move {
-            scan_port_with_timeout(ip, port, Duration::from_millis(500)).await
+impl std::error::Error for PortSpecError {}
+
+/// Create a TCP socket of the address family of the target
+fn tcp_socket(target: Target) -> io::Result<TcpSocket> {
+    if target.ip.is_ipv6() {
+        TcpSocket::new_v6()
+    } else {
+        TcpSocket::new_v4()
+    }
+}
+
+/// Scan a single port using TCP
+pub async fn scan_port(target: Target, port: u16) -> Result<ScanResult, ScanError> {
+    let socket_addr = target.socket_addr(port);
+    let (state, reason) = classify(tcp_socket(target)?.connect(socket_addr).await)?;
+    Ok(ScanResult { port, protocol: Protocol::Tcp, state, reason, retries: 0 })
+}
+
+/// Caps the number of probes in flight, across the whole scan and per host, and
+/// the rate probes are sent at. Clones share the same global budget.
+#[derive(Clone)]
+pub struct Limiter {
+    global: Arc<Semaphore>,
+    per_host: usize,
+    rate: Option<Arc<TokenBucket>>,
+    min_rate: Option<f64>,
+    floor: Option<Arc<RateFloor>>,
+    /// Probes sent so far, and how many of them before this scan started
+    sent: Arc<AtomicU64>,
+    sent_before: u64,
+    start: Instant,
+    /// Probe dispatch is held while this is set
+    paused: watch::Receiver<bool>,
+}
+
+impl Limiter {
+    pub fn new(timing: &Timing, sent: Arc<AtomicU64>, paused: watch::Receiver<bool>) -> Self {
+        Self {
+            global: Arc::new(Semaphore::new(timing.max_parallelism.max(1))),
+            per_host: timing.max_host_parallelism.max(1),
+            rate: timing.max_rate.map(|rate| Arc::new(TokenBucket::new(rate))),
+            min_rate: timing.min_rate,
+            floor: timing.min_rate.map(|rate| Arc::new(RateFloor { tokens: TokenBucket::new(rate), prepaid: AtomicU64::new(0) })),
+            sent_before: sent.load(Ordering::Relaxed),
+            sent,
+            start: Instant::now(),
+            paused,
+        }
+    }
+
+    /// Wait for a slot in the global budget, or for the minimum rate to call for
+    /// a probe beyond it, which needs no slot
+    pub async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        // the semaphore is never closed
+        let permit = async { self.global.clone().acquire_owned().await.expect("limiter closed") };
+        tokio::select! {
+            biased;
+            permit = permit => Some(permit),
+            () = self.beyond_limits() => None,
+        }
+    }
+
+    /// Wait until the scan falls behind the minimum rate far enough for another
+    /// probe, whatever the parallelism limits and scan delays. Never returns
+    /// without a minimum rate.
+    pub async fn beyond_limits(&self) {
+        let Some(floor) = &self.floor else {
+            return std::future::pending().await;
+        };
+        let _ = self.paused.clone().wait_for(|paused| !paused).await;
+        floor.tokens.take().await;
+        floor.prepaid.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Wait until the scan is not paused and the maximum rate allows another
+    /// probe, and count it as sent
+    pub async fn pace(&self) {
+        // the engine outlives its scans, so the sender is never dropped while probing
+        let _ = self.paused.clone().wait_for(|paused| !paused).await;
+        if let Some(rate) = &self.rate {
+            rate.take().await;
+        }
+        if let Some(floor) = &self.floor {
+            // probes within the limits use up the tokens of the minimum rate, so
+            // they only pile up while the scan is slower
+            let prepaid = floor.prepaid.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+            if prepaid.is_err() {
+                let _ = floor.tokens.try_take();
+            }
+        }
+        self.sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Whether the scan is sending slower than the minimum rate
+    pub fn below_min_rate(&self) -> bool {
+        self.min_rate.is_some_and(|min_rate| self.rate() < min_rate)
+    }
+
+    /// Probes per second the scan sent so far
+    pub fn rate(&self) -> f64 {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let sent = self.sent.load(Ordering::Relaxed) - self.sent_before;
+        match elapsed > 0.0 {
+            true => sent as f64 / elapsed,
+            false => 0.0,
+        }
+    }
+}
+
+/// Token bucket allowing `rate` probes per second with bursts of a tenth of a second
+struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    /// Tokens available and when they were last refilled
+    tokens: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    fn new(rate: f64) -> Self {
+        let burst = (rate / 10.0).max(1.0);
+        Self {
+            rate,
+            burst,
+            tokens: Mutex::new((burst, Instant::now())),
+        }
+    }
+
+    async fn take(&self) {
+        while let Err(wait) = self.try_take() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Take a token if there is one, or tell how long until there is
+    fn try_take(&self) -> Result<(), Duration> {
+        let mut tokens = self.tokens.lock().unwrap();
+        let (available, refilled) = *tokens;
+        let now = Instant::now();
+        let available = (available + now.duration_since(refilled).as_secs_f64() * self.rate).min(self.burst);
+        if available >= 1.0 {
+            *tokens = (available - 1.0, now);
+            return Ok(());
+        }
+        *tokens = (available, now);
+        Err(Duration::from_secs_f64((1.0 - available) / self.rate))
+    }
+}
+
+/// Tokens at the minimum rate, taken by every probe that finds one. Probes beyond
+/// the limits wait for one, so they are only sent while the scan is slower.
+struct RateFloor {
+    tokens: TokenBucket,
+    /// Tokens taken by probes beyond the limits that were not sent yet
+    prepaid: AtomicU64,
+}
+
+/// Scan the ports of a host, deriving probe timeouts from the host's round trip
+/// times and calling `on_result` with every port as soon as its state is known.
+/// Ports that could not be probed are passed to `on_error` and returned in the
+/// error state.
+pub async fn scan_ports(target: Target, ports: &[u16], limiter: &Limiter, timing: &Timing, rtt: Arc<Mutex<RttEstimator>>, mut on_result: impl FnMut(&ScanResult), mut on_error: impl FnMut(ScanError)) -> Vec<ScanResult> {
+    let host = Arc::new(Semaphore::new(limiter.per_host));
+    let backoff = Arc::new(Mutex::new(Backoff::new(timing)));
+
+    // create concurrent tasks, only as many as the host and global limits allow
+    // probes are aborted when the scan of the host is dropped, e.g. on a host timeout
+    let mut tasks = JoinSet::new();
+    let mut results = Vec::new();
+    let mut finish = |joined: Result<(usize, u16, Result<ScanResult, ScanError>), JoinError>| match joined {
+        Ok((index, _, Ok(result))) => {
+            on_result(&result);
+            results.push((index, result));
+        }
+        Ok((index, port, Err(e))) => {
+            on_error(e);
+            results.push((index, ScanResult { port, protocol: Protocol::Tcp, state: PortState::Error, reason: Reason::LocalError, retries: 0 }));
+        }
+        // only when the scan itself is aborted
+        Err(_) => {}
+    };
+    for (index, &port) in ports.iter().enumerate() {
+        while let Some(joined) = tasks.try_join_next() {
+            finish(joined);
+        }
+        let delay = backoff.lock().unwrap().delay();
+        let within_limits = async {
+            if index > 0 && !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+            // take the host slot first so a host waiting for it never holds a global slot
+            let host_permit = host.clone().acquire_owned().await.expect("limiter closed");
+            (Some(host_permit), limiter.acquire().await)
+        };
+        // a minimum rate beats politeness towards a single host
+        let (host_permit, permit) = tokio::select! {
+            biased;
+            permits = within_limits => permits,
+            () = limiter.beyond_limits() => (None, None),
+        };
+        let max_retries = timing.max_retries;
+        let (rtt, backoff, limiter) = (rtt.clone(), backoff.clone(), limiter.clone());
+        tasks.spawn(async move {
+            let result = async {
+                let mut result = probe(target, port, &rtt, &limiter).await?;
+                // an unanswered probe may just have been dropped, try again
+                let mut retries = 0;
+                while result.reason == Reason::NoResponse && retries < max_retries {
+                    retries += 1;
+                    result = probe(target, port, &rtt, &limiter).await?;
+                }
+                result.retries = retries;
+                if result.reason != Reason::NoResponse {
+                    backoff.lock().unwrap().record(retries);
+                }
+                Ok(result)
+            }.await;
+            drop((permit, host_permit));
+            (index, port, result)
         });
-        handles.push(handle);
     }
     
     // wait for all port scanning tasks to complete
-    let mut results = Vec::new();
-    for handle in handles {
-        if let Ok(result) = handle.await {
-            results.push(result);
-        }
+    while let Some(joined) = tasks.join_next().await {
+        finish(joined);
     }
     
-    results
+    // report ports in the order they were probed
+    results.sort_unstable_by_key(|&(index, _)| index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Probe a port with the current timeout of the host and feed the round trip time
+/// of an answer back into the estimate
+async fn probe(target: Target, port: u16, rtt: &Mutex<RttEstimator>, limiter: &Limiter) -> Result<ScanResult, ScanError> {
+    limiter.pace().await;
+    let timeout = rtt.lock().unwrap().timeout();
+    let start = Instant::now();
+    let result = scan_port_with_timeout(target, port, timeout).await?;
+    // both a SYN-ACK and a RST are answers from the host
+    if matches!(result.reason, Reason::SynAck | Reason::ConnRefused) {
+        rtt.lock().unwrap().update(start.elapsed());
+    }
+    Ok(result)
 }
 
-pub async fn scan_port_with_timeout(ip: IpAddr, port: u16, timeout: Duration) -> ScanResult {
-    let socket_addr = SocketAddr::new(ip, port);
-    let socket = TcpSocket::new_v4().unwrap();
+/// Scan a single port using TCP, giving up on an answer after `timeout`.
+/// Fails when the probe could not be sent, e.g. without free sockets.
+pub async fn scan_port_with_timeout(target: Target, port: u16, timeout: Duration) -> Result<ScanResult, ScanError> {
+    let socket_addr = target.socket_addr(port);
+    let socket = tcp_socket(target)?;
     
     // use tokio::time::timeout to limit the connection attempt time
-    match tokio::time::timeout(timeout, socket.connect(socket_addr)).await {
-        Ok(Ok(_)) => {
-            // println!("{}:{} is open", ip, port);
-            ScanResult { port, is_open: true }
-        },
-        _ => {
-            // timeout or connection error, consider the port as closed
-            // println!("{}:{} is closed", ip, port);
-            ScanResult { port, is_open: false }
-        }
-    }
+    let (state, reason) = match tokio::time::timeout(timeout, socket.connect(socket_addr)).await {
+        Ok(connect) => classify(connect)?,
+        // no answer at all, most likely dropped by a firewall
+        Err(_) => (PortState::Filtered, Reason::NoResponse),
+    };
+    Ok(ScanResult { port, protocol: Protocol::Tcp, state, reason, retries: 0 })
 }
 
-fn parse_ports_range(ports: &str) -> Vec<u16> {
-    let mut results = Vec::new();
-    if ports.contains('-') {
-        // Handle range expressions like "1-100"
-        let range_parts: Vec<&str> = ports.split('-').collect();
-        if range_parts.len() == 2 {
-            if let (Ok(start), Ok(end)) =
-                (range_parts[0].parse::<u16>(), range_parts[1].parse::<u16>())
-            {
-                for port in start..=end {
-                    results.push(port);
+/// Parse an nmap style port specification.
+///
+/// The specification is a comma separated list of single ports (`22`), ranges (`1-100`),
+/// open-ended ranges (`-1024`, `60000-`, or `-` for every port) and protocol prefixes
+/// (`T:22,80,U:53`) which apply to every following item until the next prefix.
+/// Items without a prefix are TCP ports. `top:N` selects the N most frequently open
+/// TCP ports. Service names (`ssh,http`) select every port registered for the service.
+/// Ports keep the order they were specified in, duplicates are dropped.
+pub fn parse_ports_range(ports: &str) -> Result<PortList, PortSpecError> {
+    let mut result = PortList::default();
+    let mut seen = HashSet::new();
+    let mut protocol = Protocol::Tcp;
+
+    if ports.trim().is_empty() {
+        return Err(PortSpecError::Empty);
+    }
+
+    for item in ports.split(',') {
+        let mut item = item.trim();
+        if let Some((prefix, count)) = item.split_once(':')
+            && prefix.eq_ignore_ascii_case("top") {
+            let count = count.trim();
+            let n = count
+                .parse::<usize>()
+                .map_err(|_| PortSpecError::InvalidTopPorts(count.to_string()))?;
+            for port in top_ports(n)? {
+                if seen.insert((Protocol::Tcp, port)) {
+                    result.tcp.push(port);
+                }
+            }
+            continue;
+        }
+        if let Some((prefix, rest)) = item.split_once(':') {
+            protocol = match prefix {
+                "T" | "t" => Protocol::Tcp,
+                "U" | "u" => Protocol::Udp,
+                _ => return Err(PortSpecError::UnknownProtocol(prefix.to_string())),
+            };
+            item = rest.trim();
+        }
+        if item.is_empty() {
+            return Err(PortSpecError::Empty);
+        }
+
+        let ports = match protocol {
+            Protocol::Tcp => &mut result.tcp,
+            Protocol::Udp => &mut result.udp,
+        };
+
+        if item.chars().any(|c| c.is_ascii_alphabetic()) {
+            let named = services::service_ports(item, protocol);
+            if named.is_empty() {
+                return Err(PortSpecError::UnknownService(item.to_string()));
+            }
+            ports.extend(named.into_iter().filter(|&port| seen.insert((protocol, port))));
+            continue;
+        }
+
+        let (start, end) = match item.split_once('-') {
+            // Handle range expressions like "1-100", "-1024", "60000-" and "-"
+            Some((start, end)) => {
+                let start = if start.is_empty() { 1 } else { parse_port(start)? };
+                let end = if end.is_empty() { u16::MAX } else { parse_port(end)? };
+                if start > end {
+                    return Err(PortSpecError::InvalidRange(item.to_string()));
                 }
+                (start, end)
+            }
+            None => {
+                let port = parse_port(item)?;
+                (port, port)
             }
+        };
+
+        ports.extend((start..=end).filter(|&port| seen.insert((protocol, port))));
+    }
+
+    Ok(result)
+}
+
+fn parse_port(port: &str) -> Result<u16, PortSpecError> {
+    port.trim()
+        .parse::<u16>()
+        .map_err(|_| PortSpecError::InvalidPort(port.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tcp(ports: &str) -> Vec<u16> {
+        parse_ports_range(ports).unwrap().tcp
+    }
+
+    #[test]
+    fn ports_and_ranges_keep_their_order() {
+        assert_eq!(tcp("80,22,1-3"), [80, 22, 1, 2, 3]);
+        assert_eq!(tcp(" 443 , 8000-8002 "), [443, 8000, 8001, 8002]);
+    }
+
+    #[test]
+    fn open_ended_ranges() {
+        assert_eq!(tcp("-3"), [1, 2, 3]);
+        assert_eq!(tcp("65534-"), [65534, 65535]);
+        assert_eq!(tcp("60000-").len(), 5536);
+        assert_eq!(tcp("-").len(), 65535);
+    }
+
+    #[test]
+    fn duplicates_are_dropped() {
+        assert_eq!(tcp("22,20-23,22"), [22, 20, 21, 23]);
+        assert_eq!(tcp("top:1,80"), [80]);
+    }
+
+    #[test]
+    fn protocol_prefixes() {
+        assert_eq!(tcp("T:22,80"), [22, 80]);
+        assert_eq!(tcp("t:22"), [22]);
+        assert_eq!(
+            parse_ports_range("T:22,U:53"),
+            Ok(PortList { tcp: vec![22], udp: vec![53] })
+        );
+        // a prefix applies to every following item
+        assert_eq!(
+            parse_ports_range("U:53,161-162,T:22,u:53"),
+            Ok(PortList { tcp: vec![22], udp: vec![53, 161, 162] })
+        );
+        // the same port of both protocols is kept for each
+        assert_eq!(
+            parse_ports_range("22,U:22,domain"),
+            Ok(PortList { tcp: vec![22], udp: vec![22, 53] })
+        );
+        assert_eq!(parse_ports_range("S:22"), Err(PortSpecError::UnknownProtocol(String::from("S"))));
+    }
+
+    #[test]
+    fn services() {
+        assert_eq!(tcp("ssh"), [22]);
+        assert!(tcp("http").contains(&80));
+        assert_eq!(parse_ports_range("nope"), Err(PortSpecError::UnknownService(String::from("nope"))));
+    }
+
+    #[test]
+    fn invalid_specifications() {
+        assert_eq!(parse_ports_range(""), Err(PortSpecError::Empty));
+        assert_eq!(parse_ports_range("22,"), Err(PortSpecError::Empty));
+        assert_eq!(parse_ports_range("T:"), Err(PortSpecError::Empty));
+        assert_eq!(parse_ports_range("65536"), Err(PortSpecError::InvalidPort(String::from("65536"))));
+        assert_eq!(parse_ports_range("100-1"), Err(PortSpecError::InvalidRange(String::from("100-1"))));
+    }
+
+    #[test]
+    fn top_ports_item() {
+        assert_eq!(tcp("top:3"), [80, 23, 443]);
+        assert_eq!(tcp("TOP:1000").len(), MAX_TOP_PORTS);
+        assert_eq!(parse_ports_range("top:0"), Err(PortSpecError::InvalidTopPorts(String::from("0"))));
+        assert_eq!(parse_ports_range("top:x"), Err(PortSpecError::InvalidTopPorts(String::from("x"))));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn token_bucket_paces_after_a_burst() {
+        let bucket = TokenBucket::new(100.0);
+        let start = Instant::now();
+        // a tenth of a second worth of probes goes out at once
+        for _ in 0..10 {
+            bucket.take().await;
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        for _ in 0..100 {
+            bucket.take().await;
         }
-    } else if let Ok(port) = ports.parse::<u16>() {
-        results.push(port);
+        let elapsed = start.elapsed().as_secs_f64();
+        assert!((0.99..1.01).contains(&elapsed), "took {}s", elapsed);
     }
-    results
-}
\ No newline at end of file
+
+    #[tokio::test(start_paused = true)]
+    async fn token_bucket_bursts_at_least_one() {
+        let bucket = TokenBucket::new(2.0);
+        let start = Instant::now();
+        for _ in 0..5 {
+            bucket.take().await;
+        }
+        let elapsed = start.elapsed().as_secs_f64();
+        assert!((1.99..2.01).contains(&elapsed), "took {}s", elapsed);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn min_rate_goes_beyond_the_parallelism() {
+        let timing = Timing { max_parallelism: 1, min_rate: Some(10.0), ..Timing::default() };
+        let (_pause, paused) = watch::channel(false);
+        let limiter = Limiter::new(&timing, Arc::new(AtomicU64::new(0)), paused);
+        let _held = limiter.acquire().await.expect("a free slot");
+        let start = Instant::now();
+        for _ in 0..10 {
+            assert!(limiter.acquire().await.is_none());
+            limiter.pace().await;
+        }
+        let elapsed = start.elapsed().as_secs_f64();
+        assert!((0.89..0.91).contains(&elapsed), "took {}s", elapsed);
+    }
+
+    #[test]
+    fn top_ports_are_distinct() {
+        let ports = top_ports(MAX_TOP_PORTS).unwrap();
+        assert_eq!(ports.len(), MAX_TOP_PORTS);
+        assert_eq!(ports[..100], TOP_TCP_PORTS_RANKED);
+        assert_eq!(ports.iter().collect::<HashSet<_>>().len(), MAX_TOP_PORTS);
+        assert!(top_ports(0).is_err());
+        assert!(top_ports(MAX_TOP_PORTS + 1).is_err());
+    }
+}
@@ -0,0 +1,211 @@
+use std::{panic, sync::{atomic::{AtomicU64, Ordering}, Arc}, time::Duration};
+
+use tokio::{sync::{mpsc::{unbounded_channel, UnboundedReceiver}, watch}, task::JoinHandle};
+
+use crate::{error::ScanError, host::HostResult, net::{self, Discovery, Report, ScanEvent}, options::ScanOptions, scan::{PortList, Technique}, timing::Timing};
+
+/// A scan of a set of targets, e.g.
+///
+/// ```no_run
+/// # async fn example() {
+/// use nmap_rs::{scan, timing::TimingTemplate, Scanner};
+///
+/// let report = Scanner::builder()
+///     .targets(["192.168.1.0/24", "example.com"])
+///     .ports(scan::parse_ports_range("top:100").unwrap())
+///     .timing(TimingTemplate::Aggressive.timing())
+///     .build()
+///     .run()
+///     .await
+///     .expect("scan failed");
+/// for host in report.hosts {
+///     println!("{} is {}: {} ports", host, host.status, host.ports.len());
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Scanner {
+    /// Target specifications, see [`crate::target::TargetSpec`]
+    pub targets: Vec<String>,
+    pub ports: PortList,
+    pub options: ScanOptions,
+}
+
+/// Results of a whole scan
+#[derive(Debug)]
+pub struct ScanReport {
+    /// Hosts in the order they were done with
+    pub hosts: Vec<HostResult>,
+    pub elapsed: Duration,
+    /// Seed of the random probe order, `--seed` repeats the scan in the same order
+    pub seed: Option<u64>,
+    /// Problems the scan went on despite, e.g. hosts that could not be pinged
+    pub warnings: Vec<ScanError>,
+}
+
+impl Scanner {
+    pub fn builder() -> ScannerBuilder {
+        ScannerBuilder::default()
+    }
+
+    /// Seed of the random probe order, `None` for a sequential scan or while the
+    /// scan is still to pick one
+    pub fn seed(&self) -> Option<u64> {
+        self.options.seed.filter(|_| self.options.randomize)
+    }
+
+    /// Run the scan to the end on the current tokio runtime. Problems that only
+    /// affect some hosts or ports end up in [`ScanReport::warnings`].
+    pub async fn run(self) -> Result<ScanReport, ScanError> {
+        let seed = self.seed();
+        let mut scan = self.spawn();
+        let mut hosts = Vec::new();
+        let mut warnings = Vec::new();
+        while let Some(event) = scan.next_event().await {
+            match event {
+                ScanEvent::HostDone(host) => hosts.push(host),
+                ScanEvent::Warning(warning) => warnings.push(warning),
+                _ => {}
+            }
+        }
+        Ok(ScanReport {
+            hosts,
+            elapsed: scan.finish().await?,
+            seed,
+            warnings,
+        })
+    }
+
+    /// Start the scan as a task on the current tokio runtime, its progress can be
+    /// followed through the events of the returned handle
+    pub fn spawn(self) -> RunningScan {
+        let (events, receiver) = unbounded_channel();
+        let (pause, paused) = watch::channel(false);
+        let probes = Arc::new(AtomicU64::new(0));
+        let report = Arc::new(move |event| {
+            let _ = events.send(event);
+        });
+        RunningScan {
+            events: receiver,
+            task: tokio::spawn(self.execute(report, probes.clone(), paused)),
+            pause,
+            probes,
+        }
+    }
+
+    /// Run the scan, reporting its progress to `report`. Probes are counted in
+    /// `probes` and held back while `paused` is set.
+    pub async fn execute(self, report: Report, probes: Arc<AtomicU64>, paused: watch::Receiver<bool>) -> Result<Duration, ScanError> {
+        net::execute_scan(report, self.targets, self.ports, self.options, probes, paused).await
+    }
+}
+
+/// A scan running on a tokio runtime. It keeps running when the handle is
+/// dropped, [`RunningScan::cancel`] stops it.
+pub struct RunningScan {
+    events: UnboundedReceiver<ScanEvent>,
+    task: JoinHandle<Result<Duration, ScanError>>,
+    pause: watch::Sender<bool>,
+    probes: Arc<AtomicU64>,
+}
+
+impl RunningScan {
+    /// Wait for the next event, `None` once the scan is over
+    pub async fn next_event(&mut self) -> Option<ScanEvent> {
+        self.events.recv().await
+    }
+
+    pub fn pause(&self) {
+        self.pause.send_replace(true);
+    }
+
+    pub fn resume(&self) {
+        self.pause.send_replace(false);
+    }
+
+    /// Abort the scan with all of its probes in flight
+    pub fn cancel(&self) {
+        self.task.abort();
+    }
+
+    pub fn probes_sent(&self) -> u64 {
+        self.probes.load(Ordering::Relaxed)
+    }
+
+    /// Wait for the scan to end, returning the time it took or why it could not
+    /// run, [`ScanError::Cancelled`] once cancelled. A panic of the scan is passed on.
+    pub async fn finish(self) -> Result<Duration, ScanError> {
+        match self.task.await {
+            Ok(outcome) => outcome,
+            Err(error) if error.is_cancelled() => Err(ScanError::Cancelled),
+            Err(error) => panic::resume_unwind(error.into_panic()),
+        }
+    }
+}
+
+/// Builder of a [`Scanner`], everything not set keeps the defaults of [`ScanOptions`]
+#[derive(Default)]
+pub struct ScannerBuilder {
+    scanner: Scanner,
+}
+
+impl ScannerBuilder {
+    /// Add target specifications: addresses, networks, octet ranges or hostnames
+    pub fn targets<T: Into<String>>(mut self, targets: impl IntoIterator<Item = T>) -> Self {
+        self.scanner.targets.extend(targets.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn ports(mut self, ports: PortList) -> Self {
+        self.scanner.ports = ports;
+        self
+    }
+
+    pub fn technique(mut self, technique: Technique) -> Self {
+        self.scanner.options.technique = technique;
+        self
+    }
+
+    pub fn timing(mut self, timing: Timing) -> Self {
+        self.scanner.options.timing = timing;
+        self
+    }
+
+    pub fn discovery(mut self, discovery: Discovery) -> Self {
+        self.scanner.options.discovery = discovery;
+        self
+    }
+
+    /// Replace every option at once, e.g. with options parsed from nmap style flags
+    pub fn options(mut self, options: ScanOptions) -> Self {
+        self.scanner.options = options;
+        self
+    }
+
+    /// The scan, with the seed of a randomized scan picked here unless one was set,
+    /// so it can be reported and the scan repeated
+    pub fn build(mut self) -> Scanner {
+        let options = &mut self.scanner.options;
+        if options.randomize {
+            options.seed.get_or_insert_with(rand::random);
+        }
+        self.scanner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::{self, PortSpecError};
+
+    #[tokio::test]
+    async fn udp_ports_are_rejected() {
+        let scan = Scanner::builder()
+            .targets(["127.0.0.1"])
+            .ports(scan::parse_ports_range("22,U:53").unwrap())
+            .build()
+            .run()
+            .await;
+        assert!(matches!(scan, Err(ScanError::Ports(PortSpecError::UnsupportedProtocol(port))) if port == "53"));
+    }
+}
@@ -0,0 +1,155 @@
+use crate::scan::Protocol;
+
+/// Well known service names by port and protocol, in the spirit of nmap-services
+const SERVICES: &[(&str, u16, Protocol)] = &[
+    ("tcpmux", 1, Protocol::Tcp),
+    ("echo", 7, Protocol::Tcp),
+    ("echo", 7, Protocol::Udp),
+    ("discard", 9, Protocol::Tcp),
+    ("discard", 9, Protocol::Udp),
+    ("daytime", 13, Protocol::Tcp),
+    ("qotd", 17, Protocol::Tcp),
+    ("chargen", 19, Protocol::Tcp),
+    ("ftp-data", 20, Protocol::Tcp),
+    ("ftp", 21, Protocol::Tcp),
+    ("ssh", 22, Protocol::Tcp),
+    ("telnet", 23, Protocol::Tcp),
+    ("smtp", 25, Protocol::Tcp),
+    ("rsftp", 26, Protocol::Tcp),
+    ("time", 37, Protocol::Tcp),
+    ("whois", 43, Protocol::Tcp),
+    ("tacacs", 49, Protocol::Tcp),
+    ("domain", 53, Protocol::Tcp),
+    ("domain", 53, Protocol::Udp),
+    ("dhcps", 67, Protocol::Udp),
+    ("dhcpc", 68, Protocol::Udp),
+    ("tftp", 69, Protocol::Udp),
+    ("gopher", 70, Protocol::Tcp),
+    ("finger", 79, Protocol::Tcp),
+    ("http", 80, Protocol::Tcp),
+    ("hosts2-ns", 81, Protocol::Tcp),
+    ("kerberos-sec", 88, Protocol::Tcp),
+    ("kerberos-sec", 88, Protocol::Udp),
+    ("pop3pw", 106, Protocol::Tcp),
+    ("pop3", 110, Protocol::Tcp),
+    ("rpcbind", 111, Protocol::Tcp),
+    ("rpcbind", 111, Protocol::Udp),
+    ("ident", 113, Protocol::Tcp),
+    ("nntp", 119, Protocol::Tcp),
+    ("ntp", 123, Protocol::Udp),
+    ("msrpc", 135, Protocol::Tcp),
+    ("netbios-ns", 137, Protocol::Udp),
+    ("netbios-dgm", 138, Protocol::Udp),
+    ("netbios-ssn", 139, Protocol::Tcp),
+    ("imap", 143, Protocol::Tcp),
+    ("news", 144, Protocol::Tcp),
+    ("snmp", 161, Protocol::Udp),
+    ("snmptrap", 162, Protocol::Udp),
+    ("bgp", 179, Protocol::Tcp),
+    ("smux", 199, Protocol::Tcp),
+    ("ldap", 389, Protocol::Tcp),
+    ("svrloc", 427, Protocol::Tcp),
+    ("https", 443, Protocol::Tcp),
+    ("snpp", 444, Protocol::Tcp),
+    ("microsoft-ds", 445, Protocol::Tcp),
+    ("kpasswd5", 464, Protocol::Tcp),
+    ("smtps", 465, Protocol::Tcp),
+    ("isakmp", 500, Protocol::Udp),
+    ("exec", 512, Protocol::Tcp),
+    ("login", 513, Protocol::Tcp),
+    ("shell", 514, Protocol::Tcp),
+    ("syslog", 514, Protocol::Udp),
+    ("printer", 515, Protocol::Tcp),
+    ("route", 520, Protocol::Udp),
+    ("klogin", 543, Protocol::Tcp),
+    ("kshell", 544, Protocol::Tcp),
+    ("afp", 548, Protocol::Tcp),
+    ("rtsp", 554, Protocol::Tcp),
+    ("submission", 587, Protocol::Tcp),
+    ("ipp", 631, Protocol::Tcp),
+    ("ldapssl", 636, Protocol::Tcp),
+    ("ldp", 646, Protocol::Tcp),
+    ("rsync", 873, Protocol::Tcp),
+    ("ftps", 990, Protocol::Tcp),
+    ("imaps", 993, Protocol::Tcp),
+    ("pop3s", 995, Protocol::Tcp),
+    ("NFS-or-IIS", 1025, Protocol::Tcp),
+    ("LSA-or-nterm", 1026, Protocol::Tcp),
+    ("IIS", 1027, Protocol::Tcp),
+    ("ms-lsa", 1029, Protocol::Tcp),
+    ("nfsd-status", 1110, Protocol::Tcp),
+    ("openvpn", 1194, Protocol::Udp),
+    ("ms-sql-s", 1433, Protocol::Tcp),
+    ("ms-sql-m", 1434, Protocol::Udp),
+    ("oracle", 1521, Protocol::Tcp),
+    ("h323q931", 1720, Protocol::Tcp),
+    ("pptp", 1723, Protocol::Tcp),
+    ("wms", 1755, Protocol::Tcp),
+    ("radius", 1812, Protocol::Udp),
+    ("upnp", 1900, Protocol::Udp),
+    ("cisco-sccp", 2000, Protocol::Tcp),
+    ("dc", 2001, Protocol::Tcp),
+    ("nfs", 2049, Protocol::Tcp),
+    ("nfs", 2049, Protocol::Udp),
+    ("ccproxy-ftp", 2121, Protocol::Tcp),
+    ("etcd-client", 2379, Protocol::Tcp),
+    ("pn-requester", 2717, Protocol::Tcp),
+    ("ppp", 3000, Protocol::Tcp),
+    ("squid-http", 3128, Protocol::Tcp),
+    ("mysql", 3306, Protocol::Tcp),
+    ("ms-wbt-server", 3389, Protocol::Tcp),
+    ("mapper-ws_ethd", 3986, Protocol::Tcp),
+    ("nat-t-ike", 4500, Protocol::Udp),
+    ("radmin", 4899, Protocol::Tcp),
+    ("upnp", 5000, Protocol::Tcp),
+    ("airport-admin", 5009, Protocol::Tcp),
+    ("ida-agent", 5051, Protocol::Tcp),
+    ("sip", 5060, Protocol::Tcp),
+    ("sip", 5060, Protocol::Udp),
+    ("admdog", 5101, Protocol::Tcp),
+    ("aol", 5190, Protocol::Tcp),
+    ("zeroconf", 5353, Protocol::Udp),
+    ("wsdapi", 5357, Protocol::Tcp),
+    ("postgresql", 5432, Protocol::Tcp),
+    ("pcanywheredata", 5631, Protocol::Tcp),
+    ("amqp", 5672, Protocol::Tcp),
+    ("nrpe", 5666, Protocol::Tcp),
+    ("vnc-http", 5800, Protocol::Tcp),
+    ("vnc", 5900, Protocol::Tcp),
+    ("X11", 6000, Protocol::Tcp),
+    ("X11:1", 6001, Protocol::Tcp),
+    ("redis", 6379, Protocol::Tcp),
+    ("realserver", 7070, Protocol::Tcp),
+    ("http-alt", 8000, Protocol::Tcp),
+    ("http", 8008, Protocol::Tcp),
+    ("ajp13", 8009, Protocol::Tcp),
+    ("http-proxy", 8080, Protocol::Tcp),
+    ("blackice-icecap", 8081, Protocol::Tcp),
+    ("https-alt", 8443, Protocol::Tcp),
+    ("sun-answerbook", 8888, Protocol::Tcp),
+    ("jetdirect", 9100, Protocol::Tcp),
+    ("wap-wsp", 9200, Protocol::Tcp),
+    ("abyss", 9999, Protocol::Tcp),
+    ("snet-sensor-mgmt", 10000, Protocol::Tcp),
+    ("memcache", 11211, Protocol::Tcp),
+    ("memcache", 11211, Protocol::Udp),
+    ("mongod", 27017, Protocol::Tcp),
+    ("filenet-tms", 32768, Protocol::Tcp),
+];
+
+/// Name of the service usually found on `port`
+pub fn service_name(port: u16, protocol: Protocol) -> Option<&'static str> {
+    SERVICES
+        .iter()
+        .find(|&&(_, p, proto)| p == port && proto == protocol)
+        .map(|&(name, _, _)| name)
+}
+
+/// Every port registered for the service `name` (case insensitive)
+pub fn service_ports(name: &str, protocol: Protocol) -> Vec<u16> {
+    SERVICES
+        .iter()
+        .filter(|&&(n, _, proto)| n.eq_ignore_ascii_case(name) && proto == protocol)
+        .map(|&(_, port, _)| port)
+        .collect()
+}
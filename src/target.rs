@@ -0,0 +1,391 @@
+use std::{collections::HashMap, ffi::CString, fmt, fs::File, io::{self, BufRead, BufReader}, net::{IpAddr, Ipv4Addr}, path::Path, sync::Arc};
+
+use ipnetwork::IpNetwork;
+
+use crate::net::Target;
+
+/// One item of an nmap style target specification
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TargetSpec {
+    /// A single address, `10.0.0.1` or `fe80::1%eth0`
+    Address(Target),
+    /// A CIDR block with the IPv6 scope of its addresses, `10.0.0.0/24`
+    Network(IpNetwork, u32),
+    /// IPv4 octet ranges, `10.0.1-4.1-254`, `192.168.0.*` or `10.0.0.1,3,5`.
+    /// Holds the sorted values allowed for each octet.
+    Octets([Vec<u8>; 4]),
+    /// A name resolved when the scan starts
+    Hostname(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TargetSpecError {
+    Empty,
+    InvalidTarget(String),
+    InvalidOctet(String),
+    InvalidScope(String),
+}
+
+impl fmt::Display for TargetSpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TargetSpecError::Empty => write!(f, "empty target specification"),
+            TargetSpecError::InvalidTarget(target) => write!(f, "invalid target {:?}", target),
+            TargetSpecError::InvalidOctet(octet) => write!(f, "invalid octet range {:?}", octet),
+            TargetSpecError::InvalidScope(scope) => write!(f, "invalid IPv6 scope {:?}", scope),
+        }
+    }
+}
+
+impl std::error::Error for TargetSpecError {}
+
+impl TargetSpec {
+    /// Parse a single address, CIDR block, octet range expression or hostname
+    pub fn parse(spec: &str) -> Result<Self, TargetSpecError> {
+        let spec = spec.trim();
+        if spec.is_empty() {
+            return Err(TargetSpecError::Empty);
+        }
+        let (addr, scope_id) = match spec.split_once('%') {
+            Some((addr, scope)) => (addr, parse_scope(scope)?),
+            None => (spec, 0),
+        };
+
+        if let Ok(ip) = addr.parse::<IpAddr>() {
+            if scope_id != 0 && ip.is_ipv4() {
+                return Err(TargetSpecError::InvalidScope(spec.to_string()));
+            }
+            return Ok(TargetSpec::Address(Target { ip, scope_id }));
+        }
+        if addr.contains('/') {
+            let network = addr
+                .parse::<IpNetwork>()
+                .map_err(|_| TargetSpecError::InvalidTarget(spec.to_string()))?;
+            if scope_id != 0 && network.is_ipv4() {
+                return Err(TargetSpecError::InvalidScope(spec.to_string()));
+            }
+            return Ok(TargetSpec::Network(network, scope_id));
+        }
+        if scope_id != 0 {
+            return Err(TargetSpecError::InvalidScope(spec.to_string()));
+        }
+        if addr.split('.').count() == 4
+            && addr.chars().all(|c| c.is_ascii_digit() || ".,-*".contains(c))
+        {
+            let mut octets: [Vec<u8>; 4] = Default::default();
+            for (octet, part) in octets.iter_mut().zip(addr.split('.')) {
+                *octet = parse_octet(part)?;
+            }
+            return Ok(TargetSpec::Octets(octets));
+        }
+        if is_hostname(addr) {
+            return Ok(TargetSpec::Hostname(addr.to_string()));
+        }
+        Err(TargetSpecError::InvalidTarget(spec.to_string()))
+    }
+
+    /// Lazily enumerate every address the specification stands for.
+    /// Hostnames have none until resolved.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = Target> + Send + '_> {
+        match self {
+            TargetSpec::Address(target) => Box::new(std::iter::once(*target)),
+            TargetSpec::Network(network, scope_id) => Box::new(
+                network.iter().map(move |ip| Target { ip, scope_id: *scope_id }),
+            ),
+            TargetSpec::Octets([a, b, c, d]) => Box::new(a.iter().flat_map(move |&a| {
+                b.iter().flat_map(move |&b| {
+                    c.iter().flat_map(move |&c| {
+                        d.iter().map(move |&d| Target {
+                            ip: IpAddr::V4(Ipv4Addr::new(a, b, c, d)),
+                            scope_id: 0,
+                        })
+                    })
+                })
+            })),
+            TargetSpec::Hostname(_) => Box::new(std::iter::empty()),
+        }
+    }
+
+    /// Number of addresses the specification stands for
+    pub fn len(&self) -> usize {
+        match self {
+            TargetSpec::Address(_) | TargetSpec::Hostname(_) => 1,
+            TargetSpec::Network(network, _) => match network.size() {
+                ipnetwork::NetworkSize::V4(size) => size as usize,
+                ipnetwork::NetworkSize::V6(size) => usize::try_from(size).unwrap_or(usize::MAX),
+            },
+            TargetSpec::Octets(octets) => octets.iter().map(Vec::len).product(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the specification covers `target`. Hostnames never match.
+    pub fn contains(&self, target: &Target) -> bool {
+        match self {
+            TargetSpec::Address(address) => address.ip == target.ip,
+            TargetSpec::Network(network, _) => network.contains(target.ip),
+            TargetSpec::Octets(octets) => match target.ip {
+                IpAddr::V4(ip) => octets
+                    .iter()
+                    .zip(ip.octets())
+                    .all(|(values, octet)| values.binary_search(&octet).is_ok()),
+                IpAddr::V6(_) => false,
+            },
+            TargetSpec::Hostname(_) => false,
+        }
+    }
+}
+
+/// Largest list whose addresses are counted one by one, a /12
+const EXACT_COUNT_LIMIT: usize = 1 << 20;
+
+/// The hosts to scan: target specifications minus exclusions
+#[derive(Clone, Debug, Default)]
+pub struct TargetList {
+    pub specs: Vec<TargetSpec>,
+    pub exclude: Vec<TargetSpec>,
+}
+
+impl TargetList {
+    /// Exclude a comma separated list of specifications, like nmap's `--exclude`
+    pub fn exclude(&mut self, specs: &str) -> Result<(), TargetSpecError> {
+        for spec in specs.split(',') {
+            self.exclude.push(TargetSpec::parse(spec)?);
+        }
+        Ok(())
+    }
+
+    pub fn is_excluded(&self, target: &Target) -> bool {
+        self.exclude.iter().any(|spec| spec.contains(target))
+    }
+
+    /// Number of addresses to scan. Larger lists than EXACT_COUNT_LIMIT are
+    /// estimated from their specifications, ignoring overlap and exclusions.
+    pub fn host_count(&self) -> usize {
+        let estimate = self.specs.iter().map(TargetSpec::len).fold(0, usize::saturating_add);
+        match estimate <= EXACT_COUNT_LIMIT {
+            true => self.addresses().count(),
+            false => estimate,
+        }
+    }
+
+    /// Lazily enumerate every address of the list that is not excluded, each once.
+    /// An address is skipped when an earlier specification already covered it, so
+    /// nothing but the specifications themselves is kept in memory.
+    pub fn addresses(&self) -> impl Iterator<Item = Target> + '_ {
+        let specs = Arc::new(SpecIndex::new(&self.specs));
+        let exclude = Arc::new(SpecIndex::new(&self.exclude));
+        self.specs.iter().enumerate().flat_map(move |(index, spec)| {
+            let (specs, exclude) = (specs.clone(), exclude.clone());
+            spec.iter().filter(move |target| {
+                !exclude.covers(target, usize::MAX) && !specs.covers(target, index)
+            })
+        })
+    }
+}
+
+/// Specifications with single addresses looked up by address, so only ranges and
+/// networks are searched, e.g. for a `-iL` list of 100k addresses
+struct SpecIndex<'a> {
+    /// Position of the first specification of each single address
+    addresses: HashMap<IpAddr, usize>,
+    ranges: Vec<(usize, &'a TargetSpec)>,
+}
+
+impl<'a> SpecIndex<'a> {
+    fn new(specs: &'a [TargetSpec]) -> Self {
+        let mut index = SpecIndex { addresses: HashMap::new(), ranges: Vec::new() };
+        for (position, spec) in specs.iter().enumerate() {
+            match spec {
+                TargetSpec::Address(target) => {
+                    index.addresses.entry(target.ip).or_insert(position);
+                }
+                spec => index.ranges.push((position, spec)),
+            }
+        }
+        index
+    }
+
+    /// Whether a specification before position `before` covers `target`
+    fn covers(&self, target: &Target, before: usize) -> bool {
+        self.addresses.get(&target.ip).is_some_and(|&position| position < before)
+            || self.ranges.iter()
+                .take_while(|(position, _)| *position < before)
+                .any(|(_, spec)| spec.contains(target))
+    }
+}
+
+/// Read whitespace separated target specifications, ignoring `#` comments
+pub fn read_specs(reader: impl BufRead) -> io::Result<Vec<String>> {
+    let mut specs = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.split('#').next().unwrap_or_default();
+        specs.extend(line.split_whitespace().map(str::to_string));
+    }
+    Ok(specs)
+}
+
+/// Read a target list like nmap's `-iL`, one or more specifications per line with
+/// `#` comments. The path `-` reads standard input.
+pub fn read_target_file(path: &Path) -> io::Result<Vec<String>> {
+    if path == Path::new("-") {
+        return read_specs(io::stdin().lock());
+    }
+    read_specs(BufReader::new(File::open(path)?))
+}
+
+/// Parse one octet of a range expression: `*`, `1-254`, `-10`, `200-` or a comma list of those
+fn parse_octet(part: &str) -> Result<Vec<u8>, TargetSpecError> {
+    let invalid = || TargetSpecError::InvalidOctet(part.to_string());
+    let mut values = Vec::new();
+    for item in part.split(',') {
+        let (start, end) = match item {
+            "*" => (0, u8::MAX),
+            _ => match item.split_once('-') {
+                Some((start, end)) => (
+                    if start.is_empty() { 0 } else { start.parse::<u8>().map_err(|_| invalid())? },
+                    if end.is_empty() { u8::MAX } else { end.parse::<u8>().map_err(|_| invalid())? },
+                ),
+                None => {
+                    let value = item.parse::<u8>().map_err(|_| invalid())?;
+                    (value, value)
+                }
+            },
+        };
+        if start > end {
+            return Err(invalid());
+        }
+        values.extend(start..=end);
+    }
+    values.sort_unstable();
+    values.dedup();
+    Ok(values)
+}
+
+/// Resolve an IPv6 zone, either an interface index (`%2`) or name (`%eth0`)
+fn parse_scope(scope: &str) -> Result<u32, TargetSpecError> {
+    if let Ok(index) = scope.parse::<u32>() {
+        return Ok(index);
+    }
+    let name = CString::new(scope).map_err(|_| TargetSpecError::InvalidScope(scope.to_string()))?;
+    // SAFETY: `name` is a valid NUL terminated string
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => Err(TargetSpecError::InvalidScope(scope.to_string())),
+        index => Ok(index),
+    }
+}
+
+/// Whether `name` is a syntactically valid DNS hostname
+pub fn is_hostname(name: &str) -> bool {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    !name.is_empty()
+        && name.len() <= 253
+        && name.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+        // a dotted quad that failed to parse as an address is not a name
+        && !name.split('.').all(|label| label.chars().all(|c| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(specs: &[&str], exclude: &str) -> TargetList {
+        let mut list = TargetList {
+            specs: specs.iter().map(|spec| TargetSpec::parse(spec).unwrap()).collect(),
+            exclude: Vec::new(),
+        };
+        if !exclude.is_empty() {
+            list.exclude(exclude).unwrap();
+        }
+        list
+    }
+
+    fn addresses(list: &TargetList) -> Vec<String> {
+        list.addresses().map(|target| target.to_string()).collect()
+    }
+
+    #[test]
+    fn single_addresses() {
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        assert_eq!(TargetSpec::parse(" 10.0.0.1 "), Ok(TargetSpec::Address(Target { ip, scope_id: 0 })));
+        let ip: IpAddr = "fe80::1".parse().unwrap();
+        assert_eq!(TargetSpec::parse("fe80::1%7"), Ok(TargetSpec::Address(Target { ip, scope_id: 7 })));
+        assert_eq!(
+            TargetSpec::parse("10.0.0.1%7"),
+            Err(TargetSpecError::InvalidScope(String::from("10.0.0.1%7")))
+        );
+    }
+
+    #[test]
+    fn networks() {
+        let spec = TargetSpec::parse("10.0.0.0/30").unwrap();
+        assert_eq!(spec.len(), 4);
+        assert!(spec.contains(&Target { ip: "10.0.0.3".parse().unwrap(), scope_id: 0 }));
+        assert!(!spec.contains(&Target { ip: "10.0.0.4".parse().unwrap(), scope_id: 0 }));
+        assert_eq!(
+            TargetSpec::parse("10.0.0.0/33"),
+            Err(TargetSpecError::InvalidTarget(String::from("10.0.0.0/33")))
+        );
+    }
+
+    #[test]
+    fn octet_ranges() {
+        let spec = TargetSpec::parse("10.0.1-4.*").unwrap();
+        assert_eq!(spec.len(), 4 * 256);
+        let first: Vec<String> = spec.iter().take(2).map(|target| target.to_string()).collect();
+        assert_eq!(first, ["10.0.1.0", "10.0.1.1"]);
+        assert_eq!(spec.iter().last().unwrap().to_string(), "10.0.4.255");
+        assert!(!spec.contains(&Target { ip: "10.0.5.1".parse().unwrap(), scope_id: 0 }));
+    }
+
+    #[test]
+    fn octets() {
+        assert_eq!(parse_octet("*").unwrap().len(), 256);
+        assert_eq!(parse_octet("5,1-3,2").unwrap(), [1, 2, 3, 5]);
+        assert_eq!(parse_octet("-2").unwrap(), [0, 1, 2]);
+        assert_eq!(parse_octet("254-").unwrap(), [254, 255]);
+        assert_eq!(parse_octet("256"), Err(TargetSpecError::InvalidOctet(String::from("256"))));
+        assert_eq!(parse_octet("3-1"), Err(TargetSpecError::InvalidOctet(String::from("3-1"))));
+        assert_eq!(parse_octet(""), Err(TargetSpecError::InvalidOctet(String::new())));
+    }
+
+    #[test]
+    fn hostnames() {
+        assert_eq!(TargetSpec::parse("scanme.nmap.org"), Ok(TargetSpec::Hostname(String::from("scanme.nmap.org"))));
+        assert_eq!(TargetSpec::parse(""), Err(TargetSpecError::Empty));
+        assert!(TargetSpec::parse("-bad-.lan").is_err());
+        // a dotted quad out of range is not a name
+        assert!(TargetSpec::parse("10.0.0.300").is_err());
+    }
+
+    #[test]
+    fn overlapping_specifications_are_listed_once() {
+        let list = list(&["10.0.0.2", "10.0.0.0/30", "10.0.0.1-2", "10.0.0.3"], "");
+        assert_eq!(addresses(&list), ["10.0.0.2", "10.0.0.0", "10.0.0.1", "10.0.0.3"]);
+        assert_eq!(list.host_count(), 4);
+    }
+
+    #[test]
+    fn exclusions() {
+        let list = list(&["10.0.0.0/29"], "10.0.0.0-2,10.0.0.6/31,10.0.0.4");
+        assert_eq!(addresses(&list), ["10.0.0.3", "10.0.0.5"]);
+        assert_eq!(list.host_count(), 2);
+        assert!(list.is_excluded(&Target { ip: "10.0.0.7".parse().unwrap(), scope_id: 0 }));
+    }
+
+    #[test]
+    fn target_files() {
+        let specs = read_specs("10.0.0.1 10.0.0.2 # gateways\n\n# none\nhost.lan\n".as_bytes()).unwrap();
+        assert_eq!(specs, ["10.0.0.1", "10.0.0.2", "host.lan"]);
+    }
+}
@@ -0,0 +1,411 @@
+use std::time::Duration;
+
+/// nmap's timing templates, `-T0` (paranoid) through `-T5` (insane)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimingTemplate {
+    Paranoid,
+    Sneaky,
+    Polite,
+    Normal,
+    Aggressive,
+    Insane,
+}
+
+impl TimingTemplate {
+    /// Parse a template by level (`4`) or name (`aggressive`)
+    pub fn parse(template: &str) -> Option<Self> {
+        match template.to_ascii_lowercase().as_str() {
+            "0" | "paranoid" => Some(TimingTemplate::Paranoid),
+            "1" | "sneaky" => Some(TimingTemplate::Sneaky),
+            "2" | "polite" => Some(TimingTemplate::Polite),
+            "3" | "normal" => Some(TimingTemplate::Normal),
+            "4" | "aggressive" => Some(TimingTemplate::Aggressive),
+            "5" | "insane" => Some(TimingTemplate::Insane),
+            _ => None,
+        }
+    }
+
+    pub fn timing(self) -> Timing {
+        match self {
+            // one probe at a time, minutes apart, to slip past an IDS
+            TimingTemplate::Paranoid => Timing {
+                initial_rtt_timeout: Duration::from_secs(300),
+                min_rtt_timeout: Duration::from_millis(100),
+                max_rtt_timeout: Duration::from_secs(300),
+                ping_timeout: Duration::from_secs(300),
+                max_parallelism: 1,
+                max_host_parallelism: 1,
+                max_retries: 10,
+                scan_delay: Duration::from_secs(300),
+                max_scan_delay: Duration::from_secs(300),
+                max_rate: None,
+                min_rate: None,
+                min_hostgroup: 1,
+                max_hostgroup: 1,
+                host_timeout: None,
+            },
+            TimingTemplate::Sneaky => Timing {
+                initial_rtt_timeout: Duration::from_secs(15),
+                min_rtt_timeout: Duration::from_millis(100),
+                max_rtt_timeout: Duration::from_secs(15),
+                ping_timeout: Duration::from_secs(15),
+                max_parallelism: 1,
+                max_host_parallelism: 1,
+                max_retries: 10,
+                scan_delay: Duration::from_secs(15),
+                max_scan_delay: Duration::from_secs(15),
+                max_rate: None,
+                min_rate: None,
+                min_hostgroup: 1,
+                max_hostgroup: 1,
+                host_timeout: None,
+            },
+            // go easy on the network and the targets
+            TimingTemplate::Polite => Timing {
+                initial_rtt_timeout: Duration::from_secs(1),
+                min_rtt_timeout: Duration::from_millis(100),
+                max_rtt_timeout: Duration::from_secs(10),
+                ping_timeout: Duration::from_secs(2),
+                max_parallelism: 1,
+                max_host_parallelism: 1,
+                max_retries: 10,
+                scan_delay: Duration::from_millis(400),
+                max_scan_delay: Duration::from_secs(1),
+                max_rate: None,
+                min_rate: None,
+                min_hostgroup: 1,
+                max_hostgroup: 64,
+                host_timeout: None,
+            },
+            TimingTemplate::Normal => Timing {
+                initial_rtt_timeout: Duration::from_secs(1),
+                min_rtt_timeout: Duration::from_millis(100),
+                max_rtt_timeout: Duration::from_secs(10),
+                ping_timeout: Duration::from_secs(2),
+                // stay well below the usual limit of 1024 open files
+                max_parallelism: 512,
+                max_host_parallelism: 128,
+                max_retries: 10,
+                scan_delay: Duration::ZERO,
+                max_scan_delay: Duration::from_secs(1),
+                max_rate: None,
+                min_rate: None,
+                min_hostgroup: 4,
+                max_hostgroup: 256,
+                host_timeout: None,
+            },
+            // for fast and reliable networks
+            TimingTemplate::Aggressive => Timing {
+                initial_rtt_timeout: Duration::from_millis(500),
+                min_rtt_timeout: Duration::from_millis(100),
+                max_rtt_timeout: Duration::from_millis(1250),
+                ping_timeout: Duration::from_secs(1),
+                max_parallelism: 768,
+                max_host_parallelism: 256,
+                max_retries: 6,
+                scan_delay: Duration::ZERO,
+                max_scan_delay: Duration::from_millis(10),
+                max_rate: None,
+                min_rate: None,
+                min_hostgroup: 8,
+                max_hostgroup: 512,
+                host_timeout: None,
+            },
+            TimingTemplate::Insane => Timing {
+                initial_rtt_timeout: Duration::from_millis(250),
+                min_rtt_timeout: Duration::from_millis(50),
+                max_rtt_timeout: Duration::from_millis(300),
+                ping_timeout: Duration::from_millis(500),
+                max_parallelism: 896,
+                max_host_parallelism: 512,
+                max_retries: 2,
+                scan_delay: Duration::ZERO,
+                max_scan_delay: Duration::from_millis(5),
+                max_rate: None,
+                min_rate: None,
+                min_hostgroup: 16,
+                max_hostgroup: 512,
+                host_timeout: Some(Duration::from_secs(15 * 60)),
+            },
+        }
+    }
+}
+
+/// Timing and performance settings of a scan
+#[derive(Clone, Debug, PartialEq)]
+pub struct Timing {
+    /// Probe timeout until round trip times of the host have been measured
+    pub initial_rtt_timeout: Duration,
+    /// Bounds of the probe timeout derived from measured round trip times
+    pub min_rtt_timeout: Duration,
+    pub max_rtt_timeout: Duration,
+    /// How long to wait for an echo reply during host discovery
+    pub ping_timeout: Duration,
+    /// Probes (pings and connection attempts) in flight across the whole scan
+    pub max_parallelism: usize,
+    /// Connection attempts in flight against a single host
+    pub max_host_parallelism: usize,
+    /// Probes resent after a timeout before a port is given up as filtered
+    pub max_retries: u32,
+    /// Pause between two probes to the same host
+    pub scan_delay: Duration,
+    /// Largest pause between probes when backing off from a host dropping probes
+    pub max_scan_delay: Duration,
+    /// Probes per second never to exceed
+    pub max_rate: Option<f64>,
+    /// Probes per second to keep up, sending beyond the parallelism limits and scan
+    /// delays while slower. A scan that still ends up slower, e.g. for lack of
+    /// probes to send, reports it.
+    pub min_rate: Option<f64>,
+    /// Hosts scanned together; groups start at the minimum and double up to the maximum
+    pub min_hostgroup: usize,
+    pub max_hostgroup: usize,
+    /// Time after which a host is given up on
+    pub host_timeout: Option<Duration>,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        TimingTemplate::Normal.timing()
+    }
+}
+
+/// Smoothed round trip time and variance of a host, as kept by TCP (RFC 6298) and
+/// nmap, used to derive how long to wait for the answer to a probe
+#[derive(Clone, Debug)]
+pub struct RttEstimator {
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    initial: Duration,
+    min: Duration,
+    max: Duration,
+}
+
+impl RttEstimator {
+    pub fn new(timing: &Timing) -> Self {
+        Self {
+            srtt: None,
+            rttvar: Duration::ZERO,
+            initial: timing.initial_rtt_timeout,
+            min: timing.min_rtt_timeout,
+            max: timing.max_rtt_timeout.max(timing.min_rtt_timeout),
+        }
+    }
+
+    /// Account for the round trip time of an answered probe
+    pub fn update(&mut self, rtt: Duration) {
+        match self.srtt {
+            None => {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2;
+            }
+            Some(srtt) => {
+                let delta = srtt.abs_diff(rtt);
+                self.rttvar = self.rttvar * 3 / 4 + delta / 4;
+                self.srtt = Some(srtt * 7 / 8 + rtt / 8);
+            }
+        }
+    }
+
+    /// Smoothed round trip time, `None` until a probe was answered
+    pub fn srtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+
+    /// How long to wait for the answer to the next probe
+    pub fn timeout(&self) -> Duration {
+        match self.srtt {
+            None => self.initial,
+            Some(srtt) => (srtt + self.rttvar * 4).clamp(self.min, self.max),
+        }
+    }
+}
+
+/// Slows down probing of a host that drops probes. A probe that was only answered
+/// after a retransmission means an earlier copy was lost; when too many answers
+/// needed one, the delay between probes to the host is doubled.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    answered: u32,
+    dropped: u32,
+    delay: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    /// Answers to collect before judging the drop rate
+    const WINDOW: u32 = 10;
+    /// Delay the backoff starts at when the template has no scan delay
+    const INITIAL_DELAY: Duration = Duration::from_millis(5);
+
+    pub fn new(timing: &Timing) -> Self {
+        Self {
+            answered: 0,
+            dropped: 0,
+            delay: timing.scan_delay,
+            max: timing.max_scan_delay.max(timing.scan_delay),
+        }
+    }
+
+    /// Account for an answered probe and how often it had to be resent
+    pub fn record(&mut self, retries: u32) {
+        self.answered += 1;
+        if retries > 0 {
+            self.dropped += 1;
+        }
+        if self.answered < Self::WINDOW {
+            return;
+        }
+        // more than 10% of the answers needed a retransmission
+        if self.dropped * 10 > self.answered {
+            self.delay = (self.delay * 2).max(Self::INITIAL_DELAY).min(self.max);
+        }
+        self.answered = 0;
+        self.dropped = 0;
+    }
+
+    /// Pause before the next probe to the host
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+}
+
+/// Parse an nmap time specification: `500ms`, `2s`, `5m`, `1h`, or a number of seconds
+pub fn parse_duration(time: &str) -> Option<Duration> {
+    let split = time.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(time.len());
+    let (value, unit) = time.split_at(split);
+    let value = value.parse::<f64>().ok()?;
+    let seconds = match unit {
+        "ms" => value / 1000.0,
+        "" | "s" => value,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("2s"), Some(Duration::from_secs(2)));
+        assert_eq!(parse_duration("1.5"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("5m"), Some(Duration::from_secs(300)));
+        assert_eq!(parse_duration("1h"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_duration("0"), Some(Duration::ZERO));
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("ms"), None);
+        assert_eq!(parse_duration("5d"), None);
+        assert_eq!(parse_duration("-1s"), None);
+    }
+
+    fn timing(min_rtt_timeout: u64, max_rtt_timeout: u64) -> Timing {
+        Timing {
+            initial_rtt_timeout: Duration::from_secs(1),
+            min_rtt_timeout: Duration::from_millis(min_rtt_timeout),
+            max_rtt_timeout: Duration::from_millis(max_rtt_timeout),
+            ..Timing::default()
+        }
+    }
+
+    #[test]
+    fn initial_timeout() {
+        let rtt = RttEstimator::new(&timing(100, 10_000));
+        assert_eq!(rtt.srtt(), None);
+        assert_eq!(rtt.timeout(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn round_trip_times_are_smoothed() {
+        let mut rtt = RttEstimator::new(&timing(100, 10_000));
+        // the first sample is taken as is, with half of it as variance
+        rtt.update(Duration::from_millis(200));
+        assert_eq!(rtt.srtt(), Some(Duration::from_millis(200)));
+        assert_eq!(rtt.timeout(), Duration::from_millis(600));
+        // srtt moves by an eighth, the variance by a quarter of the difference
+        rtt.update(Duration::from_millis(100));
+        assert_eq!(rtt.srtt(), Some(Duration::from_micros(187_500)));
+        assert_eq!(rtt.timeout(), Duration::from_micros(587_500));
+    }
+
+    #[test]
+    fn timeouts_are_bounded() {
+        let mut rtt = RttEstimator::new(&timing(100, 10_000));
+        rtt.update(Duration::from_millis(1));
+        assert_eq!(rtt.timeout(), Duration::from_millis(100));
+        let mut rtt = RttEstimator::new(&timing(100, 10_000));
+        rtt.update(Duration::from_secs(5));
+        assert_eq!(rtt.timeout(), Duration::from_secs(10));
+        // a maximum below the minimum is raised to it
+        let mut rtt = RttEstimator::new(&timing(100, 50));
+        rtt.update(Duration::from_secs(5));
+        assert_eq!(rtt.timeout(), Duration::from_millis(100));
+    }
+
+    fn backoff(scan_delay: u64, max_scan_delay: u64) -> Backoff {
+        Backoff::new(&Timing {
+            scan_delay: Duration::from_millis(scan_delay),
+            max_scan_delay: Duration::from_millis(max_scan_delay),
+            ..Timing::default()
+        })
+    }
+
+    /// Account for a window of answers, `dropped` of them after a retransmission
+    fn record_window(backoff: &mut Backoff, dropped: u32) {
+        for answer in 0..Backoff::WINDOW {
+            backoff.record(u32::from(answer < dropped));
+        }
+    }
+
+    #[test]
+    fn no_backoff_before_a_window_of_answers() {
+        let mut backoff = backoff(0, 40);
+        for _ in 1..Backoff::WINDOW {
+            backoff.record(1);
+        }
+        assert_eq!(backoff.delay(), Duration::ZERO);
+        backoff.record(1);
+        assert_eq!(backoff.delay(), Backoff::INITIAL_DELAY);
+    }
+
+    #[test]
+    fn delay_doubles_above_a_tenth_of_retransmissions() {
+        let mut backoff = backoff(0, 1000);
+        record_window(&mut backoff, 1);
+        assert_eq!(backoff.delay(), Duration::ZERO);
+        record_window(&mut backoff, 2);
+        assert_eq!(backoff.delay(), Backoff::INITIAL_DELAY);
+        record_window(&mut backoff, 2);
+        assert_eq!(backoff.delay(), Backoff::INITIAL_DELAY * 2);
+        record_window(&mut backoff, 0);
+        assert_eq!(backoff.delay(), Backoff::INITIAL_DELAY * 2);
+        // doubling starts from the scan delay of the template
+        let mut backoff = self::backoff(15, 1000);
+        record_window(&mut backoff, 2);
+        assert_eq!(backoff.delay(), Duration::from_millis(30));
+    }
+
+    #[test]
+    fn delay_is_capped() {
+        let mut backoff = backoff(0, 40);
+        for _ in 0..5 {
+            record_window(&mut backoff, Backoff::WINDOW);
+        }
+        assert_eq!(backoff.delay(), Duration::from_millis(40));
+        // a scan delay above the cap raises it
+        let mut backoff = self::backoff(100, 40);
+        record_window(&mut backoff, Backoff::WINDOW);
+        assert_eq!(backoff.delay(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn templates() {
+        assert_eq!(TimingTemplate::parse("4"), Some(TimingTemplate::Aggressive));
+        assert_eq!(TimingTemplate::parse("Insane"), Some(TimingTemplate::Insane));
+        assert_eq!(TimingTemplate::parse("6"), None);
+    }
+}
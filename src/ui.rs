@@ -1,8 +1,14 @@
-use crate::{app::{App, FocusZone}, net};
+use std::{path::Path, time::Duration};
+
+use crate::app::{App, FocusZone};
+use nmap_rs::{engine::JobState, host::{HostResult, HostStatus}, options::ScanOptions, scan::{self, PortState, ScanResult}, services, target::TargetSpec};
 use ratatui::{
-    layout::{Constraint, Layout, Rect}, style::{Color, Modifier, Style, Stylize}, text::Line, widgets::{BarChart, Block, Borders, Clear, Gauge, List, ListState, Paragraph, Widget, Wrap}, Frame
+    layout::{Constraint, Layout, Rect}, style::{Color, Modifier, Style, Stylize}, text::Line, widgets::{BarChart, Block, Borders, Gauge, List, ListState, Paragraph, Wrap}, Frame
 };
 
+/// Why `-` cannot be used as a target list in the interface
+const STDIN_UNAVAILABLE: &str = "Standard input cannot be read here, pipe targets into nmap-rs instead";
+
 pub fn draw(frame: &mut Frame, app: &mut App) {
     draw_first_tab(frame, app, frame.area());
 }
@@ -12,7 +18,7 @@ fn draw_first_tab(frame: &mut Frame, app: &mut App, area: Rect) {
         Constraint::Length(3),
         // Constraint::Min(8),
         Constraint::Length(10),
-        Constraint::Length(5),
+        Constraint::Length(7),
         Constraint::Length(5)
     ])
     .split(area);
@@ -28,15 +34,39 @@ fn draw_first_tab(frame: &mut Frame, app: &mut App, area: Rect) {
     draw_input_box(frame, app, chunks[2]);
     if app.input_trigger {
         draw_popup(frame, app, chunks[3]);
+    } else if !app.errors.is_empty() {
+        draw_errors(frame, app, chunks[3]);
     }
 }
 
 fn draw_gauges(frame: &mut Frame, app: &mut App, area: Rect) {
-    let time = app.complete_time.lock().unwrap().as_millis();
+    let time = app.complete_time.as_millis();
+    let mut title = String::from("Gauge: ");
+    match (app.job_count(JobState::Running), app.job_count(JobState::Queued)) {
+        (0, 0) => {}
+        (running, 0) => title.push_str(&format!("{} running ", running)),
+        (running, queued) => title.push_str(&format!("{} running, {} queued ", running, queued)),
+    }
+    if app.paused {
+        title.push_str("[paused] ");
+    }
+    match app.job_count(JobState::Cancelled) {
+        0 => {}
+        cancelled => title.push_str(&format!("{} cancelled ", cancelled)),
+    }
+    match app.job_count(JobState::Failed) {
+        0 => {}
+        failed => title.push_str(&format!("{} failed ", failed)),
+    }
+    if let Some(seed) = app.seed() {
+        title.push_str(&format!("(seed {})", seed));
+    }
+    let (total, done) = app.probe_totals();
+    let counters = format!("Probes : {}/{}  Open : {}", done, total, app.open_ports);
     if time > 0 {
         let label = format!("{:.2}%", app.progress * 100.0);
         let gauge = Gauge::default()
-            .block(Block::bordered().title("Gauge:").title_bottom(format!("Time : {} ms", time)))
+            .block(Block::bordered().title(title).title_bottom(format!("Time : {} ms  {}", time, counters)))
             .gauge_style(
                 Style::default()
                 .fg(Color::Magenta)
@@ -49,8 +79,12 @@ fn draw_gauges(frame: &mut Frame, app: &mut App, area: Rect) {
         frame.render_widget(gauge, area);
     } else {
         let label = format!("{:.2} %", app.progress * 100.0);
+        let eta = match app.eta() {
+            Some(eta) => format_duration(eta),
+            None => String::from("-"),
+        };
         let gauge = Gauge::default()
-            .block(Block::bordered().title("Gauge: "))
+            .block(Block::bordered().title(title).title_bottom(format!("Rate : {:.0} probes/s  ETA : {}  {}", app.rate, eta, counters)))
             .gauge_style(
                 Style::default()
                     .fg(Color::Magenta)
@@ -63,6 +97,55 @@ fn draw_gauges(frame: &mut Frame, app: &mut App, area: Rect) {
     }
 }
 
+/// Short human readable duration, e.g. `1h02m`, `3m05s` or `42s`
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+        (0, 0, seconds) => format!("{}s", seconds),
+        (0, minutes, seconds) => format!("{}m{:02}s", minutes, seconds),
+        (hours, minutes, _) => format!("{}h{:02}m", hours, minutes),
+    }
+}
+
+/// A host of the Address List, e.g. `10.0.0.5 (nas.lan) up (echo-reply)` with the
+/// status in its colour
+fn host_line(host: &HostResult) -> Line<'static> {
+    let color = match host.status {
+        HostStatus::Up => Color::Green,
+        HostStatus::Down => Color::Red,
+        HostStatus::Unknown => Color::Yellow,
+    };
+    Line::from(vec![
+        format!("{} ", host).into(),
+        host.status.to_string().fg(color),
+        format!(" ({})", host.reason).into(),
+    ])
+}
+
+/// A port of the Port List, e.g. `80/tcp open http (syn-ack)` with the state in its colour
+fn result_line(result: ScanResult) -> Line<'static> {
+    let color = match result.state {
+        PortState::Open => Color::Green,
+        PortState::Closed => Color::Red,
+        PortState::Filtered => Color::Yellow,
+        PortState::Error => Color::Magenta,
+    };
+    Line::from(vec![
+        format!("{}/{} ", result.port, result.protocol).into(),
+        result.state.to_string().fg(color),
+        format!(
+            " {} ({}{})",
+            services::service_name(result.port, result.protocol).unwrap_or("unknown"),
+            result.reason,
+            match result.retries {
+                0 => String::new(),
+                retries => format!(", {} retries", retries),
+            },
+        )
+        .into(),
+    ])
+}
+
 fn draw_charts(frame: &mut Frame, _app: &mut App, area: Rect) {
     let barchart = BarChart::default()
         .block(Block::bordered().title("Bar chart"))
@@ -96,8 +179,8 @@ fn draw_lists(frame: &mut Frame, app: &mut App, area: Rect) {
     .split(area);
 
     // list1: address list
-    let list1 = List::new(app.targets.clone())
-        .block(Block::bordered().title("Address List").title_bottom(app.targets.len().to_string()))
+    let list1 = List::new(app.hosts.iter().filter_map(|ip| app.results.get(ip)).map(host_line))
+        .block(Block::bordered().title("Address List").title_bottom(format!("{} listed, {} down", app.hosts.len(), app.down_hosts)))
         .style(Style::default().fg(Color::Cyan))
         .highlight_style(
             Style::default()
@@ -106,16 +189,17 @@ fn draw_lists(frame: &mut Frame, app: &mut App, area: Rect) {
                 .add_modifier(Modifier::ITALIC),
         );
 
-    let target = app.targets.get(app.targets_selected);
-    let data2 = match target {
-        Some(target) => match app.port_results.get(target) {
-            Some(results) => results.clone(),
-            None => Vec::new(),
-        },
-        None => Vec::new(),
-    };
-    let list2 = List::new(data2)
-        .block(Block::bordered().title("Port List"))
+    let host = app.hosts.get(app.targets_selected).and_then(|ip| app.results.get(ip));
+    let data2 = host.map(|host| host.ports.clone()).unwrap_or_default();
+    let mut title = String::from("Port List");
+    if let Some(latency) = host.and_then(|host| host.latency) {
+        title.push_str(&format!(" {:.2} ms", latency.as_secs_f64() * 1000.0));
+    }
+    if let Some(mac) = host.and_then(|host| host.mac) {
+        title.push_str(&format!(" MAC {}", mac));
+    }
+    let list2 = List::new(data2.into_iter().map(result_line))
+        .block(Block::bordered().title(title))
         .style(Style::default().fg(Color::Cyan))
         .highlight_style(
             Style::default()
@@ -125,11 +209,11 @@ fn draw_lists(frame: &mut Frame, app: &mut App, area: Rect) {
         );
 
     let mut state1 = ListState::default();
-    if !app.targets.is_empty() && app.focus_zone == FocusZone::AddressList {
+    if !app.hosts.is_empty() && app.focus_zone == FocusZone::AddressList {
         state1.select(Some(app.targets_selected));
     }
     let mut state2 = ListState::default();
-    if !app.port_results.is_empty() && app.focus_zone == FocusZone::PortList {
+    if !app.results.is_empty() && app.focus_zone == FocusZone::PortList {
         state2.select(Some(app.port_results_selected));
     }   
     
@@ -145,10 +229,18 @@ fn draw_input_box(frame: &mut Frame, app: &mut App, area: Rect) {
         "<Enter>".blue().bold(),
         " Execute ".into(),
         "<E>".blue().bold(),
+        " Cancel ".into(),
+        "<C>".blue().bold(),
+        " Pause/Resume ".into(),
+        "<P>".blue().bold(),
         " Quit ".into(),
         "<Q> ".blue().bold(),
     ]);
-    let input_list = List::new(["Address", "Port"])
+    let target_file = match app.file_targets.len() {
+        0 => String::from("Target File"),
+        count => format!("Target File ({} targets)", count),
+    };
+    let input_list = List::new([String::from("Address"), String::from("Port"), String::from("Options"), target_file])
         .block(Block::bordered().title("Input").title_bottom(instructions.centered()))
         .style(Style::default().fg(Color::Cyan))
         .highlight_style(
@@ -176,6 +268,8 @@ fn draw_popup(frame: &mut Frame, app: &mut App, area: Rect) {
     let text = match app.input_selected {
         0 => app.target_input.clone(),
         1 => app.port_input.clone(),
+        2 => app.options_input.clone(),
+        3 => app.target_file_input.clone(),
         _ => String::from("dsadsad"),
     };
     let block = popup_block(&text, app.input_selected);
@@ -183,11 +277,6 @@ fn draw_popup(frame: &mut Frame, app: &mut App, area: Rect) {
     //     .wrap(Wrap { trim: true })
     //     .style(Style::new().yellow().bg(Color::Black))
     //     .block(block);
-    let bad_popup = Block::new()
-        .title("Input Box")
-        .title_style(Style::new().white().bold())
-        .borders(Borders::ALL)
-        .border_style(Style::new().red());
 
     let paragraph = Paragraph::new(text.clone())
         .wrap(Wrap { trim: true })
@@ -196,12 +285,25 @@ fn draw_popup(frame: &mut Frame, app: &mut App, area: Rect) {
     frame.render_widget(paragraph, area); 
 }
 
+/// The most recent errors of the scans, in place of the input popup
+fn draw_errors(frame: &mut Frame, app: &App, area: Rect) {
+    let shown = area.height.saturating_sub(2) as usize;
+    let lines: Vec<Line> = app.errors.iter()
+        .skip(app.errors.len().saturating_sub(shown))
+        .map(|error| Line::from(error.as_str()))
+        .collect();
+    let paragraph = Paragraph::new(lines)
+        .style(Style::new().red().bg(Color::Black))
+        .block(Block::bordered().title(format!("Errors ({})", app.errors.len())));
+    frame.render_widget(paragraph, area);
+}
+
 fn get_memory_usage() -> f64 {
     let mut sys = sysinfo::System::new();
     sys.refresh_all();
 
-    let pid = sysinfo::get_current_pid().expect("Failed to get current process ID");
-    if let Some(process) = sys.process(pid) {
+    if let Ok(pid) = sysinfo::get_current_pid()
+        && let Some(process) = sys.process(pid) {
         let memory_kb = process.memory(); // Memory in kilobytes
         return memory_kb as f64 / 1024.0 / 1024.0;
     }
@@ -212,8 +314,8 @@ fn get_cpu_usage() -> f64 {
     let mut sys = sysinfo::System::new();
     sys.refresh_all();
 
-    let pid = sysinfo::get_current_pid().expect("Failed to get current process ID");
-    if let Some(process) = sys.process(pid) {
+    if let Ok(pid) = sysinfo::get_current_pid()
+        && let Some(process) = sys.process(pid) {
         let cpu_usage = process.cpu_usage(); // CPU usage in percentage
         return cpu_usage as f64;
     }
@@ -221,62 +323,30 @@ fn get_cpu_usage() -> f64 {
 }
 
 
-/// Helper function to create a centered rect using a percentage of the available rect
-fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
-    // Calculate the width and height based on percentages
-    let popup_width = r.width * percent_x / 100;
-    let popup_height = r.height * percent_y / 100;
-    
-    // Create a centered rect using Layout
-    let popup_rect = Rect::new(
-        // Center horizontally
-        r.x + (r.width - popup_width) / 2,
-        // Center vertically
-        r.y + (r.height - popup_height) / 2,
-        // Set width and height
-        popup_width,
-        popup_height,
-    );
-    
-    popup_rect
-}
-
-
-fn popup_block(text: &str, input_selected: usize) -> Block {
-    if input_selected == 1 {
-        return Block::new()
+fn popup_block(text: &str, input_selected: usize) -> Block<'_> {
+    let block = Block::new()
         .title("Input Box")
         .title_style(Style::new().white().bold())
         .borders(Borders::ALL)
-        .border_style(Style::new().red())
-    }
-    let targets: Vec<&str> = text.split(" ").collect();
-    let mut error_index = 0;
-    let mut error = false;
-    targets.iter().enumerate().for_each(|(index, target)| {
-        match target.parse::<ipnetwork::IpNetwork>() {
-            Ok(_) => {
-                
-            },
-            Err(e) => {
-                error_index = index;
-                error = true;
-            },
+        .border_style(Style::new().red());
+    match input_selected {
+        0 => match text.split(" ").find(|target| TargetSpec::parse(target).is_err()) {
+            Some(target) => block.title_bottom(format!("Invalid IP address {}", target)),
+            None => block,
+        },
+        1 => match scan::parse_ports_range(text) {
+            Ok(_) => block,
+            Err(e) => block.title_bottom(format!("Invalid ports: {}", e)),
+        },
+        2 => match ScanOptions::parse(text) {
+            Ok(options) if options.reads_stdin() => block.title_bottom(STDIN_UNAVAILABLE),
+            Ok(_) => block,
+            Err(e) => block.title_bottom(format!("Invalid options: {}", e)),
+        },
+        3 if text == "-" => block.title_bottom(STDIN_UNAVAILABLE),
+        3 if !text.is_empty() && !Path::new(text).is_file() => {
+            block.title_bottom(format!("Cannot read target file {}", text))
         }
-    });
-
-    if error {
-        Block::new()
-            .title("Input Box")
-            .title_style(Style::new().white().bold())
-            .borders(Borders::ALL)
-            .border_style(Style::new().red())
-            .title_bottom(format!("Invalid IP address {}", targets[error_index]))
-    } else {
-        Block::new()
-            .title("Input Box")
-            .title_style(Style::new().white().bold())
-            .borders(Borders::ALL)
-            .border_style(Style::new().red())
+        _ => block,
     }
-}
\ No newline at end of file
+}